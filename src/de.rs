@@ -1,9 +1,35 @@
 //! Deserializers and deserializer convenience functions.
+//!
+//! # Extended encodings
+//!
+//! Like the serializers, the deserializers have an `extended` mode that reads
+//! options, maps and chars in the same [encodings](crate::ser#extended-encodings).
+//! In the default mode they fail with `DeError::UnspecifiedType`.
+//! The frame functions and `FrameDecoder` read them when `DecoderConfig::extended` is set.
+//!
+//! # Limits
+//!
+//! On their own, the deserializers also read whatever lengths they're given.
+//! Their `extended` and `with_limits` methods wrap them in a `Configured`,
+//! which can enforce `Limits` on those lengths as well as allow the extended mode.
 
 pub use serde::de::Deserialize;
 
+#[macro_use]
 mod common;
 pub use common::*;
 
 mod read;
-pub use read::*;
\ No newline at end of file
+pub use read::*;
+
+mod slice;
+pub use slice::*;
+
+mod buf;
+pub use buf::*;
+
+mod frame;
+pub use frame::*;
+
+mod decoder;
+pub use decoder::*;
\ No newline at end of file
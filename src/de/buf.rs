@@ -0,0 +1,85 @@
+use super::common::*;
+use ::bytes::Buf;
+
+/// A buf deserializer can deserialize the 9p data format from any type
+/// that implements `bytes::Buf`.
+///
+/// Byte arrays are split off with `Buf::copy_to_bytes`, so when the buf is
+/// itself `Bytes`, fields deserialized with `deserialize_shared_bytes` share
+/// its memory instead of being copied.
+pub struct BufDeserializer<B: Buf> {
+    pub buf: B,
+}
+
+impl<B: Buf> BufDeserializer<B> {
+    /// Create a deserializer from the given buf.
+    pub fn new(buf: B) -> BufDeserializer<B> {
+        BufDeserializer { buf }
+    }
+
+    /// Create a deserializer from the given buf that enforces the given limits.
+    pub fn with_limits(buf: B, limits: Limits) -> Configured<BufDeserializer<B>> {
+        Configured::new(Self::new(buf)).limits(limits)
+    }
+
+    /// Wrap the deserializer to read the [extended encodings](crate::de#extended-encodings).
+    pub fn extended(self) -> Configured<BufDeserializer<B>> {
+        Configured::new(self).extended()
+    }
+
+    /// Consume the deserializer, giving back what's left of the buf.
+    pub fn into_buf(self) -> B {
+        self.buf
+    }
+
+    /// Fail with an unexpected EOF unless there are at least `len` bytes left.
+    fn need(&self, len: u64) -> Result<usize, DeError> {
+        if (self.buf.remaining() as u64) < len {
+            Err(unexpected_eof())
+        } else {
+            Ok(len as usize)
+        }
+    }
+}
+
+impl<'de, B: Buf> Source<'de> for BufDeserializer<B> {
+    fn read_into(&mut self, buf: &mut [u8]) -> Result<(), DeError> {
+        self.need(buf.len() as u64)?;
+        self.buf.copy_to_slice(buf);
+        Ok(())
+    }
+
+    fn read_chunk(&mut self, len: u64) -> Result<Chunk<'de>, DeError> {
+        let len = self.need(len)?;
+        Ok(Chunk::Shared(self.buf.copy_to_bytes(len)))
+    }
+
+    fn read_rest(&mut self, max: u64) -> Result<Chunk<'de>, DeError> {
+        let len = max.min(self.buf.remaining() as u64);
+        self.read_chunk(len)
+    }
+
+    fn skip(&mut self, len: u64) -> Result<(), DeError> {
+        let len = self.need(len)?;
+        self.buf.advance(len);
+        Ok(())
+    }
+}
+
+impl<'a, 'de: 'a, B: Buf> Deserializer<'de> for &'a mut BufDeserializer<B> {
+    deserialize_with_defaults!('de);
+}
+
+/// Deserialize from any type that implements `bytes::Buf`.
+/// ```
+/// # use nine::de::*;
+/// use bytes::Bytes;
+///
+/// let buf = Bytes::from_static(&[3, 0, 0, 0, b'f', b'o', b'o']);
+/// let data: Bytes = deserialize_shared_bytes(&mut BufDeserializer::new(buf.clone())).unwrap();
+/// assert_eq!(data, buf.slice(4..));
+/// ```
+pub fn from_buf<'de, T: Deserialize<'de>, B: Buf>(buf: B) -> Result<T, DeError> {
+    let mut des = BufDeserializer::new(buf);
+    <T as Deserialize<'de>>::deserialize(&mut des)
+}
@@ -1,8 +1,13 @@
+use crate::message::FRAME_HEADER_LEN;
+pub(crate) use crate::message::{RAW_BYTES, SIZE_PREFIXED};
+use ::bytes::Bytes;
 use serde::de::{self, *};
 pub use serde::de::{Deserialize, Deserializer};
+use std::cell::RefCell;
 use std::fmt::{self, Formatter};
 use std::io;
 use std::marker::PhantomData;
+use std::str::Utf8Error;
 use std::string::FromUtf8Error;
 use thiserror::Error;
 
@@ -39,17 +44,91 @@ where
 {
     deserializer.deserialize_byte_buf(GimmeBytesVisitor(PhantomData))
 }
-//endregion
 
-// TODO: deserialize functions for Bytes
+thread_local! {
+    /// The shared buffer a deserializer is currently visiting as bytes, if any.
+    /// Lets `SharedBytesVisitor` take a reference to it instead of copying.
+    ///
+    /// It's only ever set for the duration of the `visit_bytes` call in
+    /// `visit_shared_bytes`, and whoever uses it takes it rather than cloning it,
+    /// so it's empty again by the time the next value is deserialized.
+    /// Visitors that don't know about it, like the one for `Vec<u8>`, just copy
+    /// the slice they're given, and `visit_shared_bytes` clears it afterwards.
+    /// `SharedBytesVisitor` also checks that the slice it's given is the buffer
+    /// before using it, so it can never return the wrong bytes.
+    static VISITED_BYTES: RefCell<Option<Bytes>> = const { RefCell::new(None) };
+}
+
+/// Visit the given shared buffer as bytes, allowing `deserialize_shared_bytes`
+/// to take it without copying.
+pub(crate) fn visit_shared_bytes<'de, V: Visitor<'de>, E: Error>(
+    visitor: V,
+    bytes: Bytes,
+) -> Result<V::Value, E> {
+    VISITED_BYTES.with(|visited| *visited.borrow_mut() = Some(bytes.clone()));
+    let res = visitor.visit_bytes(&bytes);
+    VISITED_BYTES.with(|visited| visited.borrow_mut().take());
+    res
+}
+
+/// A visitor that converts byte slices / vecs into `Bytes`,
+/// sharing the deserializer's buffer if it has one.
+struct SharedBytesVisitor;
+
+impl<'de> Visitor<'de> for SharedBytesVisitor {
+    type Value = Bytes;
+    fn expecting(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(formatter, "some sorta bytes")
+    }
+
+    fn visit_bytes<E: Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+        let visited = VISITED_BYTES.with(|visited| visited.borrow_mut().take());
+        Ok(match visited {
+            Some(bytes) if bytes.as_ptr() == v.as_ptr() && bytes.len() == v.len() => bytes,
+            _ => Bytes::copy_from_slice(v),
+        })
+    }
+
+    fn visit_byte_buf<E: Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+        Ok(v.into())
+    }
+}
+
+/// A deserialize function that produces `Bytes`.
+///
+/// With a `BufDeserializer` over `Bytes`, the result is a reference-counted
+/// slice of the input rather than a copy. Other deserializers give an owned buffer.
+pub fn deserialize_shared_bytes<'de, D>(deserializer: D) -> Result<Bytes, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserializer.deserialize_byte_buf(SharedBytesVisitor)
+}
+//endregion
 
 //region Error Handling
 /// A failure at the deserialization layer.
+///
+/// Errors from within a struct come wrapped in `DeError::Context`,
+/// which says where in the message they happened,
+/// so match on `DeError::root` to find out what went wrong:
+/// ```
+/// # use nine::de::*;
+/// use nine::p2000::Rclunk;
+///
+/// let err = from_bytes::<Rclunk, _>(&[1]).unwrap_err();
+/// assert_eq!(err.context().unwrap().path(), "Rclunk.tag");
+/// assert!(err.is_eof());
+/// assert!(matches!(err.root(), DeError::Io(_)));
+/// ```
 #[derive(Error, Debug)]
 pub enum DeError {
     /// A string was requested, but the data was not valid UTF-8.
     #[error("Invalid UTF8: {0}")]
     Utf8(#[from] FromUtf8Error),
+    /// A borrowed string was requested, but the data was not valid UTF-8.
+    #[error("Invalid UTF8: {0}")]
+    Utf8Str(#[from] Utf8Error),
     /// An IO error occurred from the Read source.
     #[error("IO Error: {0}")]
     Io(#[from] io::Error),
@@ -59,14 +138,150 @@ pub enum DeError {
     /// The serde type is unspecified in 9p.
     #[error("Type {0} is unspecified in 9p")]
     UnspecifiedType(&'static str),
+    /// A frame's size field was too small to even hold its own header.
+    #[error("Frame size {0} is smaller than the frame header")]
+    FrameTooSmall(u32),
+    /// A frame's size field didn't match the number of bytes in the frame.
+    #[error("Frame size field was {size} but the frame was {actual} bytes")]
+    SizeMismatch { size: u32, actual: usize },
+    /// A frame's size field was bigger than the negotiated msize.
+    #[error("Frame size {size} exceeds the msize of {msize}")]
+    ExceedsMsize { size: u32, msize: u32 },
+    /// A frame ended before the message in it did.
+    #[error("Frame of size {0} ended before its message did")]
+    ShortFrame(u32),
+    /// A message ended before its frame did, in strict mode.
+    #[error("Message was followed by {0} unread bytes in its frame")]
+    TrailingBytes(u64),
+    /// A length or amount read was bigger than the deserializer's limit for it.
+    #[error("{kind} length {len} exceeds the limit of {limit}")]
+    LimitExceeded {
+        kind: &'static str,
+        len: u64,
+        limit: u64,
+    },
+    /// A size-prefixed value was longer than its declared size.
+    #[error("Value was longer than its declared size of {0}")]
+    SizePrefixTooSmall(u16),
+    /// A byte that should have been one of a few values, like a bool's 0 or 1, wasn't.
+    #[error("Byte {value} isn't a valid {kind}")]
+    InvalidByte { kind: &'static str, value: u8 },
+    /// A frame's type ID didn't belong to any known message type.
+    #[error("Unknown message type {0}")]
+    UnknownMessageType(u8),
+    /// An error that happened within a struct, along with where it happened.
+    ///
+    /// This wraps any of the other variants, which `DeError::root` unwraps.
+    #[error("{source} (in {context})")]
+    Context {
+        context: ErrorContext,
+        source: Box<DeError>,
+    },
+}
+
+/// Where in a message a deserialization error happened.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorContext {
+    /// The type ID of the message, if the error came from decoding a frame.
+    pub msg_type: Option<u8>,
+    /// The name of the outermost struct being deserialized.
+    pub struct_name: &'static str,
+    /// The path of fields from the outermost struct to the one that failed.
+    pub fields: Vec<&'static str>,
+    /// The offset of the field that failed, from the start of the frame
+    /// if decoding a frame, or of the input otherwise.
+    pub offset: u64,
+}
+
+impl ErrorContext {
+    /// The struct and field path of the error, such as `Rstat.stat.uid`.
+    pub fn path(&self) -> String {
+        let mut path = self.struct_name.to_string();
+        for field in &self.fields {
+            path.push('.');
+            path.push_str(field);
+        }
+        path
+    }
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{} at byte {}", self.path(), self.offset)?;
+        if let Some(msg_type) = self.msg_type {
+            write!(f, " of message type {}", msg_type)?;
+        }
+        Ok(())
+    }
 }
 
 impl DeError {
+    /// Where the error happened, if it happened within a struct.
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match self {
+            DeError::Context { context, .. } => Some(context),
+            _ => None,
+        }
+    }
+
+    /// The error itself, without any context of where it happened.
+    /// This is what to match on to handle a particular kind of error.
+    pub fn root(&self) -> &DeError {
+        match self {
+            DeError::Context { source, .. } => source.root(),
+            err => err,
+        }
+    }
+
+    /// Add the given field of the given struct to the error's path.
+    /// If the error doesn't have a context yet, the field's offset is where it happened.
+    pub(crate) fn in_field(
+        self,
+        struct_name: &'static str,
+        field: &'static str,
+        offset: u64,
+    ) -> Self {
+        match self {
+            DeError::Context {
+                mut context,
+                source,
+            } => {
+                context.struct_name = struct_name;
+                context.fields.insert(0, field);
+                DeError::Context { context, source }
+            }
+            err => DeError::Context {
+                context: ErrorContext {
+                    msg_type: None,
+                    struct_name,
+                    fields: vec![field],
+                    offset,
+                },
+                source: Box::new(err),
+            },
+        }
+    }
+
+    /// Note that the error happened in the body of a frame with the given type ID.
+    pub(crate) fn in_frame(self, msg_type: u8) -> Self {
+        match self {
+            DeError::Context {
+                mut context,
+                source,
+            } => {
+                context.msg_type = Some(msg_type);
+                context.offset += FRAME_HEADER_LEN as u64;
+                DeError::Context { context, source }
+            }
+            err => err,
+        }
+    }
+
     /// Whether or not the contained error is an io::ErrorKind::UnexpectedEof.
     /// Useful since this can merely mean the client disconnected and is not
     /// necessarily an error.
     pub fn is_eof(&self) -> bool {
-        if let DeError::Io(err) = self {
+        if let DeError::Io(err) = self.root() {
             if let io::ErrorKind::UnexpectedEof = err.kind() {
                 true
             } else {
@@ -78,9 +293,683 @@ impl DeError {
     }
 }
 
+/// Decode a bool from its 0 or 1 byte.
+pub(crate) fn decode_bool(value: u8) -> Result<bool, DeError> {
+    match value {
+        0 => Ok(false),
+        1 => Ok(true),
+        value => Err(DeError::InvalidByte {
+            kind: "bool",
+            value,
+        }),
+    }
+}
+
+/// Whether an option is present, from its 0 or 1 presence byte.
+pub(crate) fn decode_presence(value: u8) -> Result<bool, DeError> {
+    decode_bool(value).map_err(|_| DeError::InvalidByte {
+        kind: "option presence byte",
+        value,
+    })
+}
+
+/// How many bytes the UTF-8 encoded char starting with the given byte takes up.
+/// Bytes that can't start a char give 1, so that decoding it fails.
+pub(crate) fn utf8_width(first: u8) -> usize {
+    match first {
+        0xC0..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        0xF0..=0xF7 => 4,
+        _ => 1,
+    }
+}
+
+/// Decode a single char from its UTF-8 encoding, as measured by `utf8_width`.
+pub(crate) fn decode_char(bytes: &[u8]) -> Result<char, DeError> {
+    let s = std::str::from_utf8(bytes)?;
+    Ok(s.chars().next().expect("a char's encoding is never empty"))
+}
+
 impl de::Error for DeError {
     fn custom<T: fmt::Display>(msg: T) -> Self {
         DeError::DeserializeError(format!("{}", msg)).into()
     }
 }
 //endregion
+
+//region The Format
+/// Limits on what a deserializer will read, to guard against hostile
+/// length prefixes. Exceeding one fails with `DeError::LimitExceeded`
+/// before anything is allocated for it.
+///
+/// A deserializer enforces them once wrapped in a `Configured`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Limits {
+    /// The longest byte array allowed.
+    pub bytes_len: u32,
+    /// The longest string allowed.
+    pub str_len: u16,
+    /// The most elements allowed in a sequence, or entries in a map.
+    pub seq_len: u16,
+    /// The most bytes that may be read in total.
+    pub total: u64,
+}
+
+impl Default for Limits {
+    /// No limits beyond those of the format itself.
+    fn default() -> Self {
+        Limits {
+            bytes_len: u32::MAX,
+            str_len: u16::MAX,
+            seq_len: u16::MAX,
+            total: u64::MAX,
+        }
+    }
+}
+
+/// Fail unless the given length is within its limit.
+fn check_limit(kind: &'static str, len: u64, limit: u64) -> Result<(), DeError> {
+    if len > limit {
+        Err(DeError::LimitExceeded { kind, len, limit })
+    } else {
+        Ok(())
+    }
+}
+
+/// Bytes taken from a deserializer's input, in whichever form it can give them.
+pub(crate) enum Chunk<'de> {
+    /// Borrowed from input that outlives the deserializer.
+    Borrowed(&'de [u8]),
+    /// Shared with the input's buffer.
+    Shared(Bytes),
+    /// Copied out of the input.
+    Owned(Vec<u8>),
+}
+
+impl<'de> Chunk<'de> {
+    fn len(&self) -> usize {
+        match self {
+            Chunk::Borrowed(bytes) => bytes.len(),
+            Chunk::Shared(bytes) => bytes.len(),
+            Chunk::Owned(bytes) => bytes.len(),
+        }
+    }
+
+    fn visit_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        match self {
+            Chunk::Borrowed(bytes) => visitor.visit_borrowed_bytes(bytes),
+            Chunk::Shared(bytes) => visit_shared_bytes(visitor, bytes),
+            Chunk::Owned(bytes) => visitor.visit_byte_buf(bytes),
+        }
+    }
+
+    fn visit_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        match self {
+            Chunk::Borrowed(bytes) => visitor.visit_borrowed_str(std::str::from_utf8(bytes)?),
+            Chunk::Shared(bytes) => visitor.visit_str(std::str::from_utf8(&bytes)?),
+            Chunk::Owned(bytes) => visitor.visit_string(String::from_utf8(bytes)?),
+        }
+    }
+}
+
+/// Where a deserializer's bytes come from.
+///
+/// The deserializers only differ in how they take bytes from their input,
+/// so each one implements this, and `Configured` reads the format on top of it.
+/// None of these are bounded by size-prefixed values or limits; `Configured` checks
+/// those before asking for anything.
+pub(crate) trait Source<'de> {
+    /// Fill the given buffer from the input.
+    fn read_into(&mut self, buf: &mut [u8]) -> Result<(), DeError>;
+
+    /// Take exactly `len` bytes from the input.
+    fn read_chunk(&mut self, len: u64) -> Result<Chunk<'de>, DeError>;
+
+    /// Take the rest of the input, or `max` bytes of it if there's more.
+    fn read_rest(&mut self, max: u64) -> Result<Chunk<'de>, DeError>;
+
+    /// Skip exactly `len` bytes of the input.
+    fn skip(&mut self, len: u64) -> Result<(), DeError>;
+}
+
+impl<'a, 'de: 'a, S: Source<'de>> Source<'de> for &'a mut S {
+    fn read_into(&mut self, buf: &mut [u8]) -> Result<(), DeError> {
+        (**self).read_into(buf)
+    }
+    fn read_chunk(&mut self, len: u64) -> Result<Chunk<'de>, DeError> {
+        (**self).read_chunk(len)
+    }
+    fn read_rest(&mut self, max: u64) -> Result<Chunk<'de>, DeError> {
+        (**self).read_rest(max)
+    }
+    fn skip(&mut self, len: u64) -> Result<(), DeError> {
+        (**self).skip(len)
+    }
+}
+
+/// The error for input that ended too soon.
+pub(crate) fn unexpected_eof() -> DeError {
+    io::Error::from(io::ErrorKind::UnexpectedEof).into()
+}
+
+/// A deserializer that enforces `Limits`, or that reads the
+/// [extended encodings](crate::de#extended-encodings).
+///
+/// It wraps one of the other deserializers, which is kept as `inner`.
+/// ```
+/// # use nine::de::*;
+/// let limits = Limits {
+///     str_len: 4,
+///     ..Limits::default()
+/// };
+/// let mut des = SliceDeserializer::with_limits(&[5, 0, b'h', b'e', b'l', b'l', b'o'], limits);
+/// match String::deserialize(&mut des) {
+///     Err(DeError::LimitExceeded { kind: "string", .. }) => (),
+///     other => panic!("{:?}", other),
+/// }
+/// ```
+pub struct Configured<D> {
+    pub inner: D,
+    limits: Limits,
+    extended: bool,
+    /// How many bytes have been read.
+    total: u64,
+    /// Where the innermost size-prefixed value being read ends, and its declared size.
+    bound: Option<(u64, u16)>,
+}
+
+impl<D> Configured<D> {
+    /// Wrap the given deserializer, with no limits and only the standard encodings.
+    pub fn new(inner: D) -> Configured<D> {
+        Configured {
+            inner,
+            limits: Limits::default(),
+            extended: false,
+            total: 0,
+            bound: None,
+        }
+    }
+
+    /// Enforce the given limits.
+    pub fn limits(self, limits: Limits) -> Configured<D> {
+        Configured { limits, ..self }
+    }
+
+    /// Read the [extended encodings](crate::de#extended-encodings).
+    pub fn extended(self) -> Configured<D> {
+        Configured {
+            extended: true,
+            ..self
+        }
+    }
+
+    /// Consume the wrapper, giving back the deserializer.
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    /// Count the given amount towards the total about to be read,
+    /// failing if that would go past the end of the size-prefixed value being read
+    /// or over the limit.
+    fn consume(&mut self, amount: u64) -> Result<(), DeError> {
+        self.total = self.total.saturating_add(amount);
+        if let Some((end, declared)) = self.bound {
+            if self.total > end {
+                return Err(DeError::SizePrefixTooSmall(declared));
+            }
+        }
+        if self.total > self.limits.total {
+            return Err(DeError::LimitExceeded {
+                kind: "total",
+                len: self.total,
+                limit: self.limits.total,
+            });
+        }
+        Ok(())
+    }
+
+    fn require_extended(&self, type_name: &'static str) -> Result<(), DeError> {
+        if self.extended {
+            Ok(())
+        } else {
+            Err(DeError::UnspecifiedType(type_name))
+        }
+    }
+
+    fn read_exact<'de>(&mut self, buf: &mut [u8]) -> Result<(), DeError>
+    where
+        D: Source<'de>,
+    {
+        self.consume(buf.len() as u64)?;
+        self.inner.read_into(buf)
+    }
+
+    fn read_u8<'de>(&mut self) -> Result<u8, DeError>
+    where
+        D: Source<'de>,
+    {
+        let mut buf = [0; 1];
+        self.read_exact(&mut buf)?;
+        Ok(buf[0])
+    }
+
+    fn read_u16<'de>(&mut self) -> Result<u16, DeError>
+    where
+        D: Source<'de>,
+    {
+        let mut buf = [0; 2];
+        self.read_exact(&mut buf)?;
+        Ok(u16::from_le_bytes(buf))
+    }
+
+    fn read_u32<'de>(&mut self) -> Result<u32, DeError>
+    where
+        D: Source<'de>,
+    {
+        let mut buf = [0; 4];
+        self.read_exact(&mut buf)?;
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    fn read_u64<'de>(&mut self) -> Result<u64, DeError>
+    where
+        D: Source<'de>,
+    {
+        let mut buf = [0; 8];
+        self.read_exact(&mut buf)?;
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    fn read_chunk<'de>(&mut self, len: u64) -> Result<Chunk<'de>, DeError>
+    where
+        D: Source<'de>,
+    {
+        self.consume(len)?;
+        self.inner.read_chunk(len)
+    }
+}
+
+impl<'a, 'de: 'a, D: Source<'de>> Deserializer<'de> for &'a mut Configured<D> {
+    type Error = DeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(DeError::UnspecifiedType("any"))
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_bool(decode_bool(self.read_u8()?)?)
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_u8(self.read_u8()?)
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_u16(self.read_u16()?)
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_u32(self.read_u32()?)
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_u64(self.read_u64()?)
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_i8(self.read_u8()? as i8)
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_i16(self.read_u16()? as i16)
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_i32(self.read_u32()? as i32)
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_i64(self.read_u64()? as i64)
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(DeError::UnspecifiedType("f32"))
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(DeError::UnspecifiedType("f64"))
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.require_extended("char")?;
+        let mut buf = [0u8; 4];
+        buf[0] = self.read_u8()?;
+        let width = utf8_width(buf[0]);
+        self.read_exact(&mut buf[1..width])?;
+        visitor.visit_char(decode_char(&buf[..width])?)
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        let len = self.read_u16()?;
+        check_limit("string", len as u64, self.limits.str_len as u64)?;
+        self.read_chunk(len as u64)?.visit_str(visitor)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        let len = self.read_u32()?;
+        check_limit("bytes", len as u64, self.limits.bytes_len as u64)?;
+        self.read_chunk(len as u64)?.visit_bytes(visitor)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.require_extended("option")?;
+        if decode_presence(self.read_u8()?)? {
+            visitor.visit_some(self)
+        } else {
+            visitor.visit_none()
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(DeError::UnspecifiedType("unit"))
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        Err(DeError::UnspecifiedType("unit_struct"))
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        if name == RAW_BYTES {
+            // Read up to the end of the size-prefixed value being read, if any,
+            // and one past the total limit so that going over it is noticed.
+            let under_limit = self.limits.total.saturating_sub(self.total);
+            let mut available = under_limit.saturating_add(1);
+            if let Some((end, _)) = self.bound {
+                available = available.min(end - self.total);
+            }
+            let rest = self.inner.read_rest(available)?;
+            self.consume(rest.len() as u64)?;
+            return rest.visit_bytes(visitor);
+        }
+        if name != SIZE_PREFIXED {
+            return visitor.visit_newtype_struct(self);
+        }
+
+        let size = self.read_u16()?;
+        let end = self.total + size as u64;
+        if let Some((outer_end, declared)) = self.bound {
+            if end > outer_end {
+                return Err(DeError::SizePrefixTooSmall(declared));
+            }
+        }
+        let outer = self.bound.replace((end, size));
+        let res = visitor.visit_newtype_struct(&mut *self);
+        self.bound = outer;
+        let value = res?;
+
+        // Skip anything the value didn't know about, such as newer fields.
+        let unread = end - self.total;
+        self.consume(unread)?;
+        self.inner.skip(unread)?;
+        Ok(value)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        let remain = self.read_u16()?;
+        check_limit("sequence", remain as u64, self.limits.seq_len as u64)?;
+        visitor.visit_seq(CountedReader {
+            remain,
+            des: &mut *self,
+        })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_seq(self)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_seq(self)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.require_extended("map")?;
+        let remain = self.read_u16()?;
+        check_limit("map", remain as u64, self.limits.seq_len as u64)?;
+        visitor.visit_map(CountedReader {
+            remain,
+            des: &mut *self,
+        })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_seq(FieldReader {
+            name,
+            fields,
+            des: self,
+        })
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_enum(VariantReader { name, des: self })
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(DeError::UnspecifiedType("identifier"))
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(
+        self,
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        Err(DeError::UnspecifiedType("ignored_any"))
+    }
+}
+
+impl<'de, D: Source<'de>> SeqAccess<'de> for Configured<D> {
+    type Error = DeError;
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        Ok(Some(seed.deserialize(self)?))
+    }
+}
+
+/// Reads the fields of a struct in order,
+/// adding the struct and field to the path of any error.
+struct FieldReader<'a, D> {
+    name: &'static str,
+    fields: &'static [&'static str],
+    des: &'a mut Configured<D>,
+}
+
+impl<'a, 'de: 'a, D: Source<'de>> SeqAccess<'de> for FieldReader<'a, D> {
+    type Error = DeError;
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        let (field, rest) = match self.fields.split_first() {
+            Some(split) => split,
+            None => return Ok(None),
+        };
+        self.fields = rest;
+
+        let offset = self.des.total;
+        seed.deserialize(&mut *self.des)
+            .map(Some)
+            .map_err(|e| e.in_field(self.name, field, offset))
+    }
+}
+
+/// Reads an enum variant as a one-byte tag followed by its contents.
+struct VariantReader<'a, D> {
+    name: &'static str,
+    des: &'a mut Configured<D>,
+}
+
+impl<'a, 'de: 'a, D: Source<'de>> EnumAccess<'de> for VariantReader<'a, D> {
+    type Error = DeError;
+    type Variant = Self;
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self), Self::Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let tag = self.des.read_u8()?;
+        let variant = seed.deserialize(IntoDeserializer::<DeError>::into_deserializer(tag))?;
+        Ok((variant, self))
+    }
+}
+
+impl<'a, 'de: 'a, D: Source<'de>> VariantAccess<'de> for VariantReader<'a, D> {
+    type Error = DeError;
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Self::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        seed.deserialize(self.des)
+    }
+    fn tuple_variant<V>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.des.deserialize_tuple(len, visitor)
+    }
+    fn struct_variant<V>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.des.deserialize_struct(self.name, fields, visitor)
+    }
+}
+
+/// Reads the elements of a sequence, or the entries of a map, after its count.
+struct CountedReader<'a, D> {
+    remain: u16,
+    des: &'a mut Configured<D>,
+}
+
+impl<'a, 'de: 'a, D: Source<'de>> SeqAccess<'de> for CountedReader<'a, D> {
+    type Error = DeError;
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        if self.remain == 0 {
+            Ok(None)
+        } else {
+            self.remain -= 1;
+            Ok(Some(seed.deserialize(&mut *self.des)?))
+        }
+    }
+}
+
+impl<'a, 'de: 'a, D: Source<'de>> MapAccess<'de> for CountedReader<'a, D> {
+    type Error = DeError;
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        self.next_element_seed(seed)
+    }
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        seed.deserialize(&mut *self.des)
+    }
+}
+
+/// Implement `Deserializer` for a mutable reference to a `Source`
+/// by wrapping it in a `Configured` with the defaults for each value.
+macro_rules! deserialize_with_defaults {
+    ($lt:lifetime) => {
+        deserialize_with_defaults! {
+            $lt
+            deserialize_any();
+            deserialize_bool();
+            deserialize_u8();
+            deserialize_u16();
+            deserialize_u32();
+            deserialize_u64();
+            deserialize_i8();
+            deserialize_i16();
+            deserialize_i32();
+            deserialize_i64();
+            deserialize_f32();
+            deserialize_f64();
+            deserialize_char();
+            deserialize_str();
+            deserialize_string();
+            deserialize_bytes();
+            deserialize_byte_buf();
+            deserialize_option();
+            deserialize_unit();
+            deserialize_unit_struct(name: &'static str);
+            deserialize_newtype_struct(name: &'static str);
+            deserialize_seq();
+            deserialize_tuple(len: usize);
+            deserialize_tuple_struct(name: &'static str, len: usize);
+            deserialize_map();
+            deserialize_struct(name: &'static str, fields: &'static [&'static str]);
+            deserialize_enum(name: &'static str, variants: &'static [&'static str]);
+            deserialize_identifier();
+            deserialize_ignored_any();
+        }
+    };
+    ($lt:lifetime $($method:ident($($arg:ident: $ty:ty),*);)*) => {
+        type Error = $crate::de::DeError;
+        $(
+        fn $method<V: serde::de::Visitor<$lt>>(
+            self,
+            $($arg: $ty,)*
+            visitor: V,
+        ) -> Result<V::Value, Self::Error> {
+            (&mut $crate::de::Configured::new(self)).$method($($arg,)* visitor)
+        }
+        )*
+    };
+}
+//endregion
@@ -0,0 +1,109 @@
+use super::buf::*;
+use super::common::*;
+use super::frame::*;
+use crate::message::MessageSet;
+use ::bytes::{Buf, BytesMut};
+use std::marker::PhantomData;
+
+/// The result of asking a `FrameDecoder` for a message.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Decoded<M> {
+    /// A whole frame was buffered and decoded.
+    Message(M),
+    /// The next frame isn't fully buffered yet.
+    /// At least this many more bytes are needed before it can be decoded.
+    NeedMore(usize),
+}
+
+/// A decoder for a stream of frames that arrive in arbitrary chunks,
+/// such as from a non-blocking socket.
+///
+/// Chunks are buffered with `feed`, and `decode` only ever consumes whole
+/// frames, so it never blocks or leaves a message half-read.
+/// ```
+/// # use nine::de::*;
+/// use nine::p2000::{Message, Rclunk};
+///
+/// let mut decoder = FrameDecoder::<Message>::new();
+/// decoder.feed(&[7, 0, 0, 0, 121]);
+/// assert_eq!(decoder.decode().unwrap(), Decoded::NeedMore(2));
+///
+/// decoder.feed(&[1, 0]);
+/// let msg = Decoded::Message(Message::Rclunk(Rclunk { tag: 1 }));
+/// assert_eq!(decoder.decode().unwrap(), msg);
+/// ```
+#[derive(Debug)]
+pub struct FrameDecoder<M: MessageSet> {
+    buf: BytesMut,
+    config: DecoderConfig,
+    _messages: PhantomData<fn() -> M>,
+}
+
+impl<M: MessageSet> Default for FrameDecoder<M> {
+    fn default() -> Self {
+        Self::with_config(DecoderConfig::default())
+    }
+}
+
+impl<M: MessageSet> FrameDecoder<M> {
+    /// Create a decoder with an empty buffer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a decoder with an empty buffer and the given configuration.
+    pub fn with_config(config: DecoderConfig) -> Self {
+        FrameDecoder {
+            buf: BytesMut::new(),
+            config,
+            _messages: PhantomData,
+        }
+    }
+
+    /// The decoder's configuration, which may be changed between messages,
+    /// such as to set the msize once version negotiation is done.
+    pub fn config_mut(&mut self) -> &mut DecoderConfig {
+        &mut self.config
+    }
+
+    /// Buffer the given chunk of the stream.
+    pub fn feed(&mut self, chunk: &[u8]) {
+        self.buf.extend_from_slice(chunk);
+    }
+
+    /// The number of bytes buffered but not yet decoded.
+    pub fn buffered(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Decode the next message if its whole frame has been buffered.
+    ///
+    /// A frame that is fully buffered is always consumed, even if its body
+    /// fails to deserialize, so the decoder stays in step with the stream.
+    /// A frame whose size field is invalid, such as one bigger than the msize,
+    /// is refused as soon as the size is known, and is never consumed.
+    pub fn decode(&mut self) -> Result<Decoded<M>, DeError> {
+        if self.buf.len() < 4 {
+            return Ok(Decoded::NeedMore(4 - self.buf.len()));
+        }
+
+        let size = (&self.buf[..4]).get_u32_le();
+        self.config.check_size(size)?;
+        if self.buf.len() < size as usize {
+            return Ok(Decoded::NeedMore(size as usize - self.buf.len()));
+        }
+
+        let mut frame = self.buf.split_to(size as usize).freeze();
+        frame.advance(4);
+        let msg_type = frame.get_u8();
+
+        let mut des = self.config.configure(BufDeserializer::new(frame));
+        let res = M::deserialize_body(msg_type, &mut des);
+        let unread = des.inner.buf.remaining() as u64;
+
+        // The whole frame is buffered, so any EOF is the end of the frame.
+        self.config
+            .check_body(size, unread, true, res, msg_type)
+            .map(Decoded::Message)
+    }
+}
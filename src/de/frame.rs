@@ -0,0 +1,165 @@
+use super::common::*;
+use super::read::*;
+use crate::message::{MessageSet, FRAME_HEADER_LEN};
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::io::{self, Read};
+
+/// Configuration for decoding frames.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecoderConfig {
+    /// The largest frame that will be accepted, header included.
+    /// This is typically the msize agreed on by `Tversion` and `Rversion`.
+    pub msize: u32,
+    /// Limits on what is read from the body of each frame.
+    pub limits: Limits,
+    /// Whether a message that doesn't take up its whole frame is an error.
+    /// If not, any bytes left over are skipped.
+    pub strict: bool,
+    /// Whether to read the [extended encodings](crate::de#extended-encodings).
+    pub extended: bool,
+}
+
+impl Default for DecoderConfig {
+    /// A configuration that accepts frames of any size with no limits on
+    /// their contents, as is needed before an msize has been negotiated.
+    fn default() -> Self {
+        DecoderConfig {
+            msize: u32::MAX,
+            limits: Limits::default(),
+            strict: false,
+            extended: false,
+        }
+    }
+}
+
+impl DecoderConfig {
+    /// A configuration that refuses frames bigger than the given msize.
+    pub fn with_msize(msize: u32) -> Self {
+        DecoderConfig {
+            msize,
+            ..Self::default()
+        }
+    }
+
+    /// Wrap the given deserializer so it reads a frame's body under this configuration.
+    pub(crate) fn configure<D>(&self, des: D) -> Configured<D> {
+        let des = Configured::new(des).limits(self.limits.clone());
+        if self.extended {
+            des.extended()
+        } else {
+            des
+        }
+    }
+
+    /// Check that the size field of a frame is valid under this configuration.
+    pub(crate) fn check_size(&self, size: u32) -> Result<(), DeError> {
+        if size < FRAME_HEADER_LEN {
+            Err(DeError::FrameTooSmall(size))
+        } else if size > self.msize {
+            Err(DeError::ExceedsMsize {
+                size,
+                msize: self.msize,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Check the outcome of deserializing a frame's body, given how many bytes
+    /// of the body were left unread and whether it was the end of the frame,
+    /// rather than the end of the stream, that was hit if there was an EOF.
+    pub(crate) fn check_body<M>(
+        &self,
+        size: u32,
+        unread: u64,
+        frame_ended: bool,
+        res: Result<Option<M>, DeError>,
+        msg_type: u8,
+    ) -> Result<M, DeError> {
+        match res {
+            Err(ref err) if err.is_eof() && frame_ended => Err(DeError::ShortFrame(size)),
+            Err(err) => Err(err.in_frame(msg_type)),
+            Ok(None) => Err(DeError::UnknownMessageType(msg_type)),
+            Ok(Some(_)) if self.strict && unread > 0 => Err(DeError::TrailingBytes(unread)),
+            Ok(Some(msg)) => Ok(msg),
+        }
+    }
+}
+
+/// Read a whole message frame, `size[4] type[1]` header included,
+/// and deserialize it into whichever message of the set its type ID names.
+/// ```
+/// # use nine::de::*;
+/// use nine::p2000::{Message, Rclunk};
+///
+/// let frame = [7u8, 0, 0, 0, 121, 1, 0];
+/// let msg: Message = read_message(&frame[..]).unwrap();
+/// assert_eq!(msg, Message::Rclunk(Rclunk { tag: 1 }));
+/// ```
+pub fn read_message<M: MessageSet, R: Read>(reader: R) -> Result<M, DeError> {
+    read_message_with(reader, &DecoderConfig::default())
+}
+
+/// Read a whole message frame like `read_message`, using the given configuration.
+///
+/// A frame whose size field exceeds the msize is refused before its body is read.
+/// Otherwise, the message is never read past the end of its frame,
+/// and whatever is left of the frame is skipped,
+/// so the reader is left at the start of the next frame.
+pub fn read_message_with<M: MessageSet, R: Read>(
+    mut reader: R,
+    config: &DecoderConfig,
+) -> Result<M, DeError> {
+    let size = reader.read_u32::<LittleEndian>()?;
+    config.check_size(size)?;
+    let msg_type = reader.read_u8()?;
+
+    let mut body = reader.take((size - FRAME_HEADER_LEN) as u64);
+    let res = M::deserialize_body(
+        msg_type,
+        &mut config.configure(ReadDeserializer::new(&mut body)),
+    );
+    let unread = body.limit();
+    io::copy(&mut body, &mut io::sink())?;
+
+    config.check_body(size, unread, unread == 0, res, msg_type)
+}
+
+/// Deserialize a message from a byte slice holding exactly one frame.
+///
+/// Fails if the frame's size field doesn't match the length of the slice.
+pub fn from_frame<M: MessageSet, B: AsRef<[u8]>>(bytes: B) -> Result<M, DeError> {
+    from_frame_with(bytes, &DecoderConfig::default())
+}
+
+/// Deserialize a message from a byte slice holding exactly one frame,
+/// using the given configuration.
+/// ```
+/// # use nine::de::*;
+/// use nine::p2000::Message;
+///
+/// let frame = [8u8, 0, 0, 0, 121, 1, 0, 0xFF];
+/// let config = DecoderConfig {
+///     strict: true,
+///     ..DecoderConfig::default()
+/// };
+/// match from_frame_with::<Message, _>(&frame, &config) {
+///     Err(DeError::TrailingBytes(1)) => (),
+///     _ => panic!(),
+/// }
+/// ```
+pub fn from_frame_with<M: MessageSet, B: AsRef<[u8]>>(
+    bytes: B,
+    config: &DecoderConfig,
+) -> Result<M, DeError> {
+    let bytes = bytes.as_ref();
+    let size = (&bytes[..]).read_u32::<LittleEndian>()?;
+    if size as usize != bytes.len() {
+        return Err(DeError::SizeMismatch {
+            size,
+            actual: bytes.len(),
+        });
+    }
+
+    read_message_with(bytes, config)
+}
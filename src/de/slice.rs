@@ -0,0 +1,74 @@
+use super::common::*;
+
+/// A slice deserializer deserializes the 9p data format from an in-memory
+/// byte slice. Strings and byte arrays are borrowed from the slice instead
+/// of being copied, so types like `&'de str` and `&'de [u8]` can be deserialized.
+///
+/// The slice is advanced past everything that is deserialized.
+pub struct SliceDeserializer<'de> {
+    pub input: &'de [u8],
+}
+
+impl<'de> SliceDeserializer<'de> {
+    /// Create a deserializer from the given slice.
+    pub fn new(input: &'de [u8]) -> SliceDeserializer<'de> {
+        SliceDeserializer { input }
+    }
+
+    /// Create a deserializer from the given slice that enforces the given limits.
+    pub fn with_limits(input: &'de [u8], limits: Limits) -> Configured<SliceDeserializer<'de>> {
+        Configured::new(Self::new(input)).limits(limits)
+    }
+
+    /// Wrap the deserializer to read the [extended encodings](crate::de#extended-encodings).
+    pub fn extended(self) -> Configured<SliceDeserializer<'de>> {
+        Configured::new(self).extended()
+    }
+
+    /// Split off the next `len` bytes of the input.
+    fn take(&mut self, len: u64) -> Result<&'de [u8], DeError> {
+        if (self.input.len() as u64) < len {
+            return Err(unexpected_eof());
+        }
+        let (taken, rest) = self.input.split_at(len as usize);
+        self.input = rest;
+        Ok(taken)
+    }
+}
+
+impl<'de> Source<'de> for SliceDeserializer<'de> {
+    fn read_into(&mut self, buf: &mut [u8]) -> Result<(), DeError> {
+        buf.copy_from_slice(self.take(buf.len() as u64)?);
+        Ok(())
+    }
+
+    fn read_chunk(&mut self, len: u64) -> Result<Chunk<'de>, DeError> {
+        self.take(len).map(Chunk::Borrowed)
+    }
+
+    fn read_rest(&mut self, max: u64) -> Result<Chunk<'de>, DeError> {
+        let len = max.min(self.input.len() as u64);
+        self.read_chunk(len)
+    }
+
+    fn skip(&mut self, len: u64) -> Result<(), DeError> {
+        self.take(len).map(drop)
+    }
+}
+
+impl<'a, 'de: 'a> Deserializer<'de> for &'a mut SliceDeserializer<'de> {
+    deserialize_with_defaults!('de);
+}
+
+/// Deserialize from a byte slice, borrowing strings and byte arrays from it.
+/// ```
+/// # use nine::de::*;
+///
+/// let bytes = [3u8, 0, b'f', b'o', b'o'];
+/// let name: &str = from_slice(&bytes).unwrap();
+/// assert_eq!(name, "foo");
+/// ```
+pub fn from_slice<'de, T: Deserialize<'de>>(bytes: &'de [u8]) -> Result<T, DeError> {
+    let mut des = SliceDeserializer::new(bytes);
+    <T as Deserialize<'de>>::deserialize(&mut des)
+}
@@ -3,4 +3,5 @@
 pub mod de;
 pub mod p2000;
 pub mod message;
+pub mod registry;
 pub mod ser;
\ No newline at end of file
@@ -1,19 +1,37 @@
 extern crate nine;
 
-use byteorder::{LittleEndian, WriteBytesExt};
+use nine::message::Protocol;
 use nine::{de::*, p2000::*, ser::*};
 use std::env::{args, var};
+use std::error::Error;
 use std::io::{stdin, stdout, BufRead, Read, Write};
 use std::os::unix::net::UnixStream; // TODO: abstract so this still works on windows
+use std::process::exit;
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
 
 trait SimpleClient {
-    fn version(&mut self);
-    fn attach(&mut self, uname: String) -> u32;
-    fn walk(&mut self, fid: u32, newfid: u32, wname: Vec<String>) -> Vec<Qid>;
-    fn stat(&mut self, fid: u32) -> Stat;
-    fn open(&mut self, fid: u32, mode: OpenMode) -> (Qid, u32);
-    fn read(&mut self, fid: u32, offset: u64, count: u32) -> Vec<u8>;
-    fn write(&mut self, fid: u32, offset: u64, data: Vec<u8>) -> u32;
+    fn version(&mut self) -> Result<()>;
+    fn attach(&mut self, uname: String) -> Result<u32>;
+    fn walk(&mut self, fid: u32, newfid: u32, wname: Vec<String>) -> Result<Vec<Qid>>;
+    fn stat(&mut self, fid: u32) -> Result<Stat>;
+    fn open(&mut self, fid: u32, mode: OpenMode) -> Result<(Qid, u32)>;
+    fn read(&mut self, fid: u32, offset: u64, count: u32) -> Result<Vec<u8>>;
+    fn write(&mut self, fid: u32, offset: u64, data: Vec<u8>) -> Result<u32>;
+}
+
+/// Read the response to a request, which has to be either
+/// the given message or an `Rerror`, which becomes an error.
+macro_rules! response {
+    ($client:expr, $variant:ident) => {
+        match $client.read_msg()? {
+            Message::$variant(msg) => msg,
+            Message::Rerror(err) => return Err(err.ename.into()),
+            other => {
+                return Err(format!("expected {}, got {:?}", stringify!($variant), other).into())
+            }
+        }
+    };
 }
 
 struct Client<Stream>
@@ -23,7 +41,8 @@ where
 {
     stream: Stream,
     msg_buf: Vec<u8>,
-    msize: u32,
+    encoder: EncoderConfig,
+    decoder: DecoderConfig,
 }
 
 impl<Stream: Write + Read> Client<Stream> {
@@ -31,51 +50,48 @@ impl<Stream: Write + Read> Client<Stream> {
         Client {
             stream,
             msg_buf: Vec::new(),
-            msize: u32::max_value(),
+            encoder: EncoderConfig::default(),
+            decoder: DecoderConfig::default(),
         }
     }
 
-    fn send_msg<T: Serialize + MessageTypeId>(&mut self, t: &T) -> Result<(), SerErrorWithIo> {
+    fn send_msg<T: Serialize + MessageTypeId>(
+        &mut self,
+        t: &T,
+    ) -> std::result::Result<(), SerErrorWithIo> {
         self.msg_buf.truncate(0);
-        let amt = into_vec(&t, &mut self.msg_buf)?;
+        append_frame_with(t, &mut self.msg_buf, &self.encoder)?;
 
-        assert!(self.msize >= amt);
-        self.stream.write_u32::<LittleEndian>(amt + 5)?;
-        self.stream.write_u8(t.msg_type_id())?;
-        Ok(self.stream.write_all(&self.msg_buf[0..amt as usize])?)
+        Ok(self.stream.write_all(&self.msg_buf)?)
     }
 
-    fn read_msg<'de, T: Deserialize<'de> + ConstMessageTypeId>(&mut self) -> Result<T, DeError> {
-        let _size: u32 = self.read_a()?;
-        let mtype: u8 = self.read_a()?;
-        assert_eq!(mtype, <T as ConstMessageTypeId>::MSG_TYPE_ID);
-
-        self.read_a()
-    }
-
-    fn read_a<'de, T: Deserialize<'de>>(&mut self) -> Result<T, DeError> {
-        from_reader(&mut self.stream)
+    fn read_msg(&mut self) -> std::result::Result<<P2000 as Protocol>::Message, DeError> {
+        read_message_with::<<P2000 as Protocol>::Message, _>(&mut self.stream, &self.decoder)
     }
 }
 
 impl<Stream: Write + Read> SimpleClient for Client<Stream> {
-    fn version(&mut self) {
+    fn version(&mut self) -> Result<()> {
         let tversion = Tversion {
             tag: 0,
-            msize: self.msize,
-            version: "9P2000".into(),
+            msize: self.encoder.msize,
+            version: P2000::VERSION.into(),
         };
 
-        self.send_msg(&tversion).unwrap();
-        let rversion: Rversion = self.read_msg().unwrap();
+        self.send_msg(&tversion)?;
+        let rversion = response!(self, Rversion);
 
-        assert_eq!(rversion.version, "9P2000");
-        if rversion.msize > self.msize {
-            self.msize = rversion.msize;
+        if rversion.version != P2000::VERSION {
+            return Err(format!("unsupported version {:?}", rversion.version).into());
+        }
+        if rversion.msize < self.encoder.msize {
+            self.encoder.msize = rversion.msize;
         }
+        self.decoder.msize = self.encoder.msize;
+        Ok(())
     }
 
-    fn attach(&mut self, uname: String) -> u32 {
+    fn attach(&mut self, uname: String) -> Result<u32> {
         let attach = Tattach {
             tag: 0,
             fid: 0,
@@ -84,61 +100,61 @@ impl<Stream: Write + Read> SimpleClient for Client<Stream> {
             aname: "/".into(),
         };
 
-        self.send_msg(&attach).unwrap();
-        self.read_msg::<Rattach>().unwrap();
+        self.send_msg(&attach)?;
+        response!(self, Rattach);
 
-        0
+        Ok(0)
     }
-    fn walk(&mut self, fid: u32, newfid: u32, wname: Vec<String>) -> Vec<Qid> {
+    fn walk(&mut self, fid: u32, newfid: u32, wname: Vec<String>) -> Result<Vec<Qid>> {
         let walk = Twalk {
             tag: 0,
             fid,
             newfid,
             wname,
         };
-        self.send_msg(&walk).unwrap();
+        self.send_msg(&walk)?;
 
-        let rwalk: Rwalk = self.read_msg().unwrap();
+        let rwalk = response!(self, Rwalk);
 
-        rwalk.wqid
+        Ok(rwalk.wqid)
     }
-    fn stat(&mut self, fid: u32) -> Stat {
+    fn stat(&mut self, fid: u32) -> Result<Stat> {
         let stat = Tstat { tag: 0, fid };
-        self.send_msg(&stat).unwrap();
-        let stat: Rstat = self.read_msg().unwrap();
+        self.send_msg(&stat)?;
+        let stat = response!(self, Rstat);
 
-        stat.stat
+        Ok(stat.stat)
     }
-    fn open(&mut self, fid: u32, mode: OpenMode) -> (Qid, u32) {
+    fn open(&mut self, fid: u32, mode: OpenMode) -> Result<(Qid, u32)> {
         let open = Topen { tag: 0, fid, mode };
-        self.send_msg(&open).unwrap();
-        let ropen: Ropen = self.read_msg().unwrap();
+        self.send_msg(&open)?;
+        let ropen = response!(self, Ropen);
 
-        (ropen.qid, ropen.iounit)
+        Ok((ropen.qid, ropen.iounit))
     }
-    fn read(&mut self, fid: u32, offset: u64, count: u32) -> Vec<u8> {
+    fn read(&mut self, fid: u32, offset: u64, count: u32) -> Result<Vec<u8>> {
         let read = Tread {
             tag: 0,
             fid,
             offset,
             count,
         };
-        self.send_msg(&read).unwrap();
-        let read: Rread = self.read_msg().unwrap();
+        self.send_msg(&read)?;
+        let read = response!(self, Rread);
 
-        read.data
+        Ok(read.data)
     }
-    fn write(&mut self, fid: u32, offset: u64, data: Vec<u8>) -> u32 {
+    fn write(&mut self, fid: u32, offset: u64, data: Vec<u8>) -> Result<u32> {
         let twrite = Twrite {
             tag: 0,
             fid,
             offset,
             data,
         };
-        self.send_msg(&twrite).unwrap();
-        let rwrite: Rwrite = self.read_msg().unwrap();
+        self.send_msg(&twrite)?;
+        let rwrite = response!(self, Rwrite);
 
-        rwrite.count
+        Ok(rwrite.count)
     }
 }
 
@@ -181,56 +197,63 @@ fn main() {
 
     // TODO: handle optional dial, although that requires -A support
 
-    let mut client = Client::new(UnixStream::connect(dial.unwrap()).unwrap());
+    let stream = UnixStream::connect(dial.unwrap()).unwrap();
+    if let Err(err) = run(Client::new(stream), &subcommand.unwrap(), path) {
+        eprintln!("9p: {}", err);
+        exit(1);
+    }
+}
 
+fn run<C: SimpleClient>(mut client: C, subcommand: &str, path: Vec<String>) -> Result<()> {
     let whoami = var("USER").unwrap();
 
-    client.version();
-    let root = client.attach(whoami);
+    client.version()?;
+    let root = client.attach(whoami)?;
 
     let mut stdout = stdout();
-    match subcommand.unwrap().as_ref() {
+    match subcommand {
         "read" => {
             let len = path.len();
             let fid = 1;
-            let qids = client.walk(root, fid, path);
+            let qids = client.walk(root, fid, path)?;
             assert_eq!(len, qids.len());
 
-            let stat = client.stat(fid);
-            client.open(fid, OpenMode::READ);
+            let stat = client.stat(fid)?;
+            client.open(fid, OpenMode::READ)?;
 
             let file_size = stat.length;
             let mut offset = 0;
             while offset < file_size {
-                let bytes = client.read(fid, 0, u32::max_value().min(file_size as u32));
+                let bytes = client.read(fid, 0, u32::max_value().min(file_size as u32))?;
                 offset += bytes.len() as u64;
-                stdout.write(bytes.as_ref()).unwrap();
+                stdout.write_all(bytes.as_ref())?;
             }
         }
         "write" => {
             let len = path.len();
             let fid = 1;
-            let qids = client.walk(root, fid, path);
+            let qids = client.walk(root, fid, path)?;
             assert_eq!(len, qids.len());
 
-            client.open(fid, OpenMode::WRITE);
+            client.open(fid, OpenMode::WRITE)?;
 
             // TODO: -l for line by line only
             let stdin = stdin();
             let mut offset = 0;
-            for mut line in stdin.lock().lines().map(Result::unwrap) {
+            for line in stdin.lock().lines() {
+                let mut line = line?;
                 line.push_str("\n");
-                offset += client.write(fid, offset, line.into_bytes()) as u64;
+                offset += client.write(fid, offset, line.into_bytes())? as u64;
             }
         }
         "stat" => {
             // TODO: copy p9p format
             let len = path.len();
             let fid = 1;
-            let qids = client.walk(root, fid, path);
+            let qids = client.walk(root, fid, path)?;
             assert_eq!(len, qids.len());
 
-            let stat = client.stat(fid);
+            let stat = client.stat(fid)?;
             println!("{:?}", stat);
         },
         "rdwr" => {
@@ -245,4 +268,5 @@ fn main() {
         "rmdir" => unimplemented!(),
         _ => panic!(),
     }
+    Ok(())
 }
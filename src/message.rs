@@ -1,7 +1,24 @@
 //! `message` contains traits and macros used to help define messages.
 
+use serde::de::{
+    Deserialize, DeserializeOwned, DeserializeSeed, Deserializer, EnumAccess, Error, Unexpected,
+    VariantAccess, Visitor,
+};
+use serde::ser::{Serialize, Serializer};
+use std::fmt::{self, Formatter};
+use std::marker::PhantomData;
+
+/// The length of the `size[4] type[1]` header that starts every message frame.
+pub const FRAME_HEADER_LEN: u32 = 5;
+
 pub trait MessageTypeId {
     fn msg_type_id(&self) -> u8;
+
+    /// Whether the message's serialized form already starts with its type ID,
+    /// as a message set's does, so that a frame shouldn't repeat it.
+    fn serializes_type_id(&self) -> bool {
+        false
+    }
 }
 
 pub trait ConstMessageTypeId {
@@ -14,6 +31,302 @@ impl<T: ConstMessageTypeId> MessageTypeId for T {
     }
 }
 
+/// A message that may carry a bulk data payload, such as `Rread` or `Twrite`.
+///
+/// The payload must be the message's last field and its only byte array,
+/// so that it can be written straight from the message rather than
+/// copied in with the rest of the frame.
+pub trait Payload {
+    /// The bulk data carried by the message, which is empty if it has none.
+    fn payload(&self) -> &[u8];
+}
+
+/// The newtype struct name that marks a value as size-prefixed.
+/// The serializers and deserializers in this crate prefix any newtype struct
+/// with this name by its two-byte size.
+pub const SIZE_PREFIXED: &str = "nine::SizePrefixed";
+
+/// A value whose serialized form is prefixed by its two-byte size,
+/// not counting the prefix itself, like each stat returned by a directory read.
+///
+/// Nesting it gives two levels of size prefix, like the stat in `Rstat` and `Twstat`.
+/// For fields, `size_prefixed` and `double_size_prefixed` can be used with
+/// `#[serde(with = "...")]` instead.
+///
+/// When deserializing, the value isn't allowed to read past its declared size,
+/// and whatever it leaves unread is skipped, so that newer formats with
+/// extra trailing fields can still be read.
+/// ```
+/// # use nine::ser::*;
+/// use nine::message::SizePrefixed;
+/// let bytes = into_bytes(&SizePrefixed(7u32)).unwrap();
+/// assert_eq!(bytes, [4, 0, 7, 0, 0, 0]);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SizePrefixed<T>(pub T);
+
+impl<T: Serialize> Serialize for SizePrefixed<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(SIZE_PREFIXED, &self.0)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for SizePrefixed<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_newtype_struct(SIZE_PREFIXED, SizePrefixedVisitor(PhantomData))
+    }
+}
+
+struct SizePrefixedVisitor<T>(PhantomData<T>);
+
+impl<'de, T: Deserialize<'de>> Visitor<'de> for SizePrefixedVisitor<T> {
+    type Value = SizePrefixed<T>;
+
+    fn expecting(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(formatter, "a size-prefixed value")
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Self::Value, D::Error> {
+        T::deserialize(deserializer).map(SizePrefixed)
+    }
+}
+
+/// The newtype struct name that marks a value as the raw rest of the input.
+/// The deserializers in this crate visit any newtype struct with this name
+/// with all the bytes left in the frame, or in the size-prefixed value being read.
+/// The serializers write a byte array directly inside one without its length prefix.
+pub const RAW_BYTES: &str = "nine::RawBytes";
+
+/// Bytes with no length prefix that run to the end of whatever holds them,
+/// like the body of a message whose type isn't known.
+///
+/// Since nothing says where they end, they can only come last.
+/// ```
+/// # use nine::ser::*;
+/// # use nine::de::*;
+/// use nine::message::RawBytes;
+/// let bytes = into_bytes(&(1u16, RawBytes(vec![2, 3]))).unwrap();
+/// assert_eq!(bytes, [1, 0, 2, 3]);
+/// let (_, RawBytes(rest)): (u16, RawBytes) = from_bytes(&bytes).unwrap();
+/// assert_eq!(rest, [2, 3]);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RawBytes(pub Vec<u8>);
+
+impl Serialize for RawBytes {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        RawSlice(&self.0).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for RawBytes {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_newtype_struct(RAW_BYTES, RawBytesVisitor)
+    }
+}
+
+/// Serializes borrowed bytes the way `RawBytes` does, as a byte array
+/// in a newtype struct named `RAW_BYTES`, which the serializers in this crate
+/// write without its length prefix.
+pub(crate) struct RawSlice<'a>(pub &'a [u8]);
+
+impl<'a> Serialize for RawSlice<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(RAW_BYTES, &ByteArray(self.0))
+    }
+}
+
+/// A byte slice that serializes as a byte array rather than a sequence.
+struct ByteArray<'a>(&'a [u8]);
+
+impl<'a> Serialize for ByteArray<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+struct RawBytesVisitor;
+
+impl<'de> Visitor<'de> for RawBytesVisitor {
+    type Value = RawBytes;
+
+    fn expecting(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(formatter, "the rest of the input")
+    }
+
+    fn visit_bytes<E: Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+        Ok(RawBytes(v.to_vec()))
+    }
+
+    fn visit_byte_buf<E: Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+        Ok(RawBytes(v))
+    }
+}
+
+/// Serialize a field with one size prefix, using `#[serde(with = "...")]`.
+pub mod size_prefixed {
+    use super::*;
+
+    pub fn serialize<T: Serialize, S: Serializer>(t: &T, serializer: S) -> Result<S::Ok, S::Error> {
+        SizePrefixed(t).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        SizePrefixed::deserialize(deserializer).map(|SizePrefixed(t)| t)
+    }
+}
+
+/// Serialize a field with two levels of size prefix, using `#[serde(with = "...")]`.
+pub mod double_size_prefixed {
+    use super::*;
+
+    pub fn serialize<T: Serialize, S: Serializer>(t: &T, serializer: S) -> Result<S::Ok, S::Error> {
+        SizePrefixed(SizePrefixed(t)).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        SizePrefixed::deserialize(deserializer).map(|SizePrefixed(SizePrefixed(t))| t)
+    }
+}
+
+/// A set of messages that can be told apart by their type ID,
+/// typically an enum with one variant per message type.
+pub trait MessageSet: Sized {
+    /// The type IDs of the messages in this set.
+    const MSG_TYPE_IDS: &'static [u8];
+
+    /// Deserialize the body of the message with the given type ID.
+    ///
+    /// Returns `None` if the type ID isn't part of this set.
+    fn deserialize_body<'de, D: Deserializer<'de>>(
+        msg_type: u8,
+        deserializer: D,
+    ) -> Result<Option<Self>, D::Error>;
+}
+
+/// A dialect of 9p, such as 9P2000 or 9P2000.L, which ties the version string
+/// negotiated by `Tversion` and `Rversion` to the messages that make it up.
+///
+/// Clients and servers can be generic over the dialect they speak.
+/// ```
+/// # use nine::de::*;
+/// # use nine::ser::*;
+/// use nine::message::Protocol;
+/// use nine::p2000::{Message, Rclunk, P2000};
+///
+/// fn decode<P: Protocol>(frame: &[u8]) -> Result<P::Message, DeError> {
+///     from_frame(frame)
+/// }
+///
+/// let frame = into_frame(&Rclunk { tag: 1 }).unwrap();
+/// assert_eq!(decode::<P2000>(&frame).unwrap(), Message::Rclunk(Rclunk { tag: 1 }));
+/// assert_eq!(P2000::VERSION, "9P2000");
+/// assert_eq!(P2000::response_type_id(120), Some(121));
+/// ```
+pub trait Protocol {
+    /// The version string that selects this dialect.
+    const VERSION: &'static str;
+
+    /// The type ID of the message sent in response to any request that fails,
+    /// such as `Rerror`.
+    const ERROR_TYPE_ID: u8;
+
+    /// Every message in this dialect.
+    type Message: MessageSet + MessageTypeId + Serialize + DeserializeOwned;
+
+    /// Deserialize the body of the message with the given type ID.
+    ///
+    /// Returns `None` if the type ID isn't part of this dialect.
+    fn deserialize_body<'de, D: Deserializer<'de>>(
+        msg_type: u8,
+        deserializer: D,
+    ) -> Result<Option<Self::Message>, D::Error> {
+        Self::Message::deserialize_body(msg_type, deserializer)
+    }
+
+    /// Whether the given type ID belongs to this dialect.
+    fn has_type_id(msg_type: u8) -> bool {
+        Self::Message::MSG_TYPE_IDS.contains(&msg_type)
+    }
+
+    /// The type ID of the response to the request with the given type ID,
+    /// or `None` if it isn't a request in this dialect.
+    ///
+    /// By default, requests have even type IDs and their responses
+    /// have the type ID right after, as in all of 9p's dialects.
+    fn response_type_id(request_type: u8) -> Option<u8> {
+        let response_type = request_type.checked_add(1)?;
+        if request_type & 1 == 0
+            && Self::has_type_id(request_type)
+            && Self::has_type_id(response_type)
+        {
+            Some(response_type)
+        } else {
+            None
+        }
+    }
+
+    /// Whether a message with the given type ID is a valid response
+    /// to the request with the given type ID, either its usual response or an error.
+    fn is_response_to(request_type: u8, response_type: u8) -> bool {
+        match Self::response_type_id(request_type) {
+            Some(expected) => response_type == expected || response_type == Self::ERROR_TYPE_ID,
+            None => false,
+        }
+    }
+}
+
+/// Deserializes a message set from an enum variant whose tag is the message type ID.
+/// Used by `message_set!`.
+#[doc(hidden)]
+pub struct MessageSetVisitor<M>(pub PhantomData<M>);
+
+impl<'de, M: MessageSet> Visitor<'de> for MessageSetVisitor<M> {
+    type Value = M;
+
+    fn expecting(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(formatter, "a message type ID followed by its message")
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Self::Value, A::Error> {
+        let (msg_type, variant) = data.variant::<u8>()?;
+        variant.newtype_variant_seed(MessageBodySeed {
+            msg_type,
+            _messages: PhantomData::<M>,
+        })
+    }
+}
+
+/// Deserializes the body of the message with the given type ID.
+struct MessageBodySeed<M> {
+    msg_type: u8,
+    _messages: PhantomData<M>,
+}
+
+impl<'de, M: MessageSet> DeserializeSeed<'de> for MessageBodySeed<M> {
+    type Value = M;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<M, D::Error> {
+        M::deserialize_body(self.msg_type, deserializer)?.ok_or_else(|| {
+            D::Error::invalid_value(
+                Unexpected::Unsigned(self.msg_type as u64),
+                &"a known message type ID",
+            )
+        })
+    }
+}
+
 /// Allows you to write message type IDs all at once, similar
 /// to how they'd be written in in Fcall.h.
 #[macro_export]
@@ -88,4 +401,95 @@ macro_rules! messages {
             )*
         }
     }
-}
\ No newline at end of file
+}
+
+/// Declares an enum with one newtype variant per message type, named after
+/// the type it holds. Implements `MessageSet` and `MessageTypeId` for it,
+/// and `From` for each message type.
+///
+/// It (de)serializes as an enum whose variant tag is the message type ID,
+/// which is to say as the message it holds, preceded by its type ID.
+#[macro_export]
+macro_rules! message_set {
+    {
+        $(#[$meta:meta])*
+        pub enum $name:ident {
+            $(
+            $variant:ident,
+            )*
+        }
+    } => {
+        $(#[$meta])*
+        pub enum $name {
+            $(
+            $variant($variant),
+            )*
+        }
+
+        impl $crate::message::MessageSet for $name {
+            const MSG_TYPE_IDS: &'static [u8] = &[
+                $(<$variant as $crate::message::ConstMessageTypeId>::MSG_TYPE_ID,)*
+            ];
+
+            fn deserialize_body<'de, D: serde::de::Deserializer<'de>>(
+                msg_type: u8,
+                deserializer: D,
+            ) -> Result<Option<Self>, D::Error> {
+                $(
+                if msg_type == <$variant as $crate::message::ConstMessageTypeId>::MSG_TYPE_ID {
+                    return serde::de::Deserialize::deserialize(deserializer)
+                        .map(|msg| Some($name::$variant(msg)));
+                }
+                )*
+                Ok(None)
+            }
+        }
+
+        impl $crate::message::MessageTypeId for $name {
+            fn msg_type_id(&self) -> u8 {
+                match self {
+                    $(
+                    $name::$variant(msg) => $crate::message::MessageTypeId::msg_type_id(msg),
+                    )*
+                }
+            }
+
+            fn serializes_type_id(&self) -> bool {
+                true
+            }
+        }
+
+        impl serde::ser::Serialize for $name {
+            fn serialize<S: serde::ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                match self {
+                    $(
+                    $name::$variant(msg) => serializer.serialize_newtype_variant(
+                        stringify!($name),
+                        $crate::message::MessageTypeId::msg_type_id(msg) as u32,
+                        stringify!($variant),
+                        msg,
+                    ),
+                    )*
+                }
+            }
+        }
+
+        impl<'de> serde::de::Deserialize<'de> for $name {
+            fn deserialize<D: serde::de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                deserializer.deserialize_enum(
+                    stringify!($name),
+                    &[$(stringify!($variant),)*],
+                    $crate::message::MessageSetVisitor::<Self>(std::marker::PhantomData),
+                )
+            }
+        }
+
+        $(
+        impl From<$variant> for $name {
+            fn from(msg: $variant) -> Self {
+                $name::$variant(msg)
+            }
+        }
+        )*
+    }
+}
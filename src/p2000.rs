@@ -4,10 +4,13 @@ pub mod l;
 pub mod u;
 
 use super::{de::*, ser::*};
-pub use crate::message::{ConstMessageTypeId, MessageTypeId, Taggable};
+pub use crate::message::{
+    ConstMessageTypeId, MessageTypeId, Payload, Protocol, SizePrefixed, Taggable,
+};
 use bitflags::bitflags;
-use enum_dispatch::enum_dispatch;
 use serde::{Deserialize, Serialize};
+use std::io;
+use std::marker::PhantomData;
 
 /// The tag number used to represent that tags are irrelevant for this message.
 pub const NOTAG: u16 = !0u16;
@@ -23,6 +26,8 @@ bitflags! {
       //const SKIPPED   = 0b00010000;
         const AUTH      = 0b00001000;
         const TEMPORARY = 0b00000100;
+        /// 9P2000.u only.
+        const SYMLINK   = 0b00000010;
     }
 }
 
@@ -65,6 +70,14 @@ bitflags! {
         const AUTH = 1 << 27;
         const TMP = 1 << 26;
 
+        // 9P2000.u only
+        const SYMLINK = 1 << 25;
+        const DEVICE = 1 << 23;
+        const NAMED_PIPE = 1 << 21;
+        const SOCKET = 1 << 20;
+        const SETUID = 1 << 19;
+        const SETGID = 1 << 18;
+
         const OWNER_READ  = 1 << 8;
         const OWNER_WRITE = 1 << 7;
         const OWNER_EXEC  = 1 << 6;
@@ -98,6 +111,10 @@ pub struct Qid {
     pub path: u64,
 }
 
+/// The metadata of a file, as returned by `Tstat` and by reading a directory.
+///
+/// A stat is always sent with its size in front, which is left to
+/// whatever holds it: wrap it in a `SizePrefixed` to (de)serialize it on its own.
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct Stat {
     pub type_: u16,
@@ -131,6 +148,134 @@ impl Stat {
     }
 }
 
+/// The outcome of packing directory entries with `pack_dir_entries`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PackedEntries {
+    /// How many entries were packed. The next read should resume
+    /// with the entry after these.
+    pub entries: usize,
+    /// How many bytes the packed entries take up.
+    pub len: u32,
+}
+
+/// Pack as many of the given stats as fit within `count` bytes onto the end
+/// of the given buffer, the way they're returned by reading a directory:
+/// each one prefixed by its size, and never split across reads.
+/// Dialects with their own stat, like 9P2000.u, are packed the same way.
+///
+/// If the first stat doesn't fit, nothing is packed,
+/// which a server would typically report as an error.
+/// ```
+/// # use nine::p2000::*;
+/// # let stat = Stat {
+/// #     type_: 0, dev: 0,
+/// #     qid: Qid { file_type: FileType::FILE, version: 0, path: 0 },
+/// #     mode: FileMode::OWNER_READ, atime: 0, mtime: 0, length: 0,
+/// #     name: "file".into(), uid: "glenda".into(), gid: "glenda".into(), muid: "glenda".into(),
+/// # };
+/// let stats = vec![stat.clone(), stat.clone(), stat];
+/// let mut data = Vec::new();
+/// let packed = pack_dir_entries(&stats, 150, &mut data).unwrap();
+/// assert_eq!(packed.entries, 2);
+/// assert_eq!(packed.len as usize, data.len());
+///
+/// let rest = pack_dir_entries(&stats[packed.entries..], 150, &mut data).unwrap();
+/// assert_eq!(rest.entries, 1);
+/// ```
+pub fn pack_dir_entries<'a, I, S>(
+    stats: I,
+    count: u32,
+    buf: &mut Vec<u8>,
+) -> Result<PackedEntries, SerError>
+where
+    I: IntoIterator<Item = &'a S>,
+    S: Serialize + 'a,
+{
+    let mut packed = PackedEntries { entries: 0, len: 0 };
+    for stat in stats {
+        let size = serialized_size(&SizePrefixed(stat))?;
+        match packed.len.checked_add(size) {
+            Some(len) if len <= count => packed.len = len,
+            _ => break,
+        }
+        append_vec(&SizePrefixed(stat), &mut *buf)?;
+        packed.entries += 1;
+    }
+    Ok(packed)
+}
+
+/// An iterator over the stats in the data returned by reading a directory.
+///
+/// Stops after the first error, since the size of a bad entry can't be trusted.
+/// ```
+/// # use nine::p2000::*;
+/// # let stat = Stat {
+/// #     type_: 0, dev: 0,
+/// #     qid: Qid { file_type: FileType::FILE, version: 0, path: 0 },
+/// #     mode: FileMode::OWNER_READ, atime: 0, mtime: 0, length: 0,
+/// #     name: "file".into(), uid: "glenda".into(), gid: "glenda".into(), muid: "glenda".into(),
+/// # };
+/// let mut data = Vec::new();
+/// pack_dir_entries(&[stat.clone(), stat.clone()], 8192, &mut data).unwrap();
+///
+/// let stats: Vec<Stat> = DirEntries::new(&data).collect::<Result<_, _>>().unwrap();
+/// assert_eq!(stats, vec![stat.clone(), stat]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct DirEntries<'a, S = Stat> {
+    data: &'a [u8],
+    stat: PhantomData<S>,
+}
+
+impl<'a> DirEntries<'a> {
+    /// Iterate over the stats in the given directory data, such as `Rread.data`.
+    pub fn new(data: &'a [u8]) -> DirEntries<'a> {
+        DirEntries::with_stats(data)
+    }
+}
+
+impl<'a, S> DirEntries<'a, S> {
+    /// Iterate over the stats of another dialect, such as `u::Stat`,
+    /// in the given directory data.
+    pub fn with_stats(data: &'a [u8]) -> DirEntries<'a, S> {
+        DirEntries {
+            data,
+            stat: PhantomData,
+        }
+    }
+
+    /// The data that hasn't been iterated over yet.
+    pub fn remaining(&self) -> &'a [u8] {
+        self.data
+    }
+}
+
+impl<'a, S: Deserialize<'a>> Iterator for DirEntries<'a, S> {
+    type Item = Result<S, DeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+        let size = match from_slice::<u16>(self.data) {
+            Ok(size) => size as usize + 2,
+            Err(err) => {
+                self.data = &[];
+                return Some(Err(err));
+            }
+        };
+        if size > self.data.len() {
+            self.data = &[];
+            return Some(Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()));
+        }
+
+        let (entry, rest) = self.data.split_at(size);
+        let res = from_slice(entry).map(|SizePrefixed(stat)| stat);
+        self.data = if res.is_ok() { rest } else { &[] };
+        Some(res)
+    }
+}
+
 crate::messages! {
     #[derive(Debug, PartialEq, Eq)]
      Tversion {
@@ -158,6 +303,7 @@ crate::messages! {
     }
     #[derive(Debug, PartialEq, Eq)]
      Tflush {
+        oldtag: u16,
     }
     #[derive(Debug, PartialEq, Eq)]
      Rflush {
@@ -253,11 +399,13 @@ crate::messages! {
     }
     #[derive(Debug, PartialEq, Eq)]
      Rstat {
+        #[serde(with = "crate::message::double_size_prefixed")]
         stat: Stat,
     }
     #[derive(Debug, PartialEq, Eq)]
      Twstat {
         fid: u32,
+        #[serde(with = "crate::message::double_size_prefixed")]
         stat: Stat,
     }
     #[derive(Debug, PartialEq, Eq)]
@@ -311,46 +459,81 @@ crate::message_type_ids! {
     Rwstat = 127
 }
 
-#[enum_dispatch(MessageTypeId)]
-pub enum Message {
-    Tversion(Tversion),
-    Rversion(Rversion),
+crate::message_set! {
+    /// Any 9p2000 message.
+    #[derive(Debug, PartialEq, Eq)]
+    pub enum Message {
+        Tversion,
+        Rversion,
+
+        Tauth,
+        Rauth,
+
+        Tattach,
+        Rattach,
+
+        Rerror,
+
+        Tflush,
+        Rflush,
+
+        Twalk,
+        Rwalk,
 
-    Tauth(Tauth),
-    Rauth(Rauth),
+        Topen,
+        Ropen,
 
-    Tattach(Tattach),
-    Rattach(Rattach),
+        Tcreate,
+        Rcreate,
 
-    Rerror(Rerror),
+        Tread,
+        Rread,
 
-    Tflush(Tflush),
-    Rflush(Rflush),
+        Twrite,
+        Rwrite,
 
-    Twalk(Twalk),
-    Rwalk(Rwalk),
+        Tclunk,
+        Rclunk,
 
-    Topen(Topen),
-    Ropen(Ropen),
+        Tremove,
+        Rremove,
 
-    Tcreate(Tcreate),
-    Rcreate(Rcreate),
+        Tstat,
+        Rstat,
 
-    Tread(Tread),
-    Rread(Rread),
+        Twstat,
+        Rwstat,
+    }
+}
 
-    Twrite(Twrite),
-    Rwrite(Rwrite),
+/// The 9P2000 dialect, made up of the messages in `Message`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct P2000;
 
-    Tclunk(Tclunk),
-    Rclunk(Rclunk),
+impl Protocol for P2000 {
+    const VERSION: &'static str = "9P2000";
+    const ERROR_TYPE_ID: u8 = Rerror::MSG_TYPE_ID;
+    type Message = Message;
+}
 
-    Tremove(Tremove),
-    Rremove(Rremove),
+impl Payload for Rread {
+    fn payload(&self) -> &[u8] {
+        &self.data
+    }
+}
 
-    Tstat(Tstat),
-    Rstat(Rstat),
+impl Payload for Twrite {
+    fn payload(&self) -> &[u8] {
+        &self.data
+    }
+}
 
-    Twstat(Twstat),
-    Rwstat(Rwstat),
+impl Payload for Message {
+    fn payload(&self) -> &[u8] {
+        match self {
+            Message::Rread(msg) => msg.payload(),
+            Message::Twrite(msg) => msg.payload(),
+            _ => &[],
+        }
+    }
 }
@@ -1,8 +1,22 @@
+//! The messages of 9P2000.L, the dialect spoken by Linux's v9fs, laid out as in `net/9p`.
+//!
+//! Only the 9P2000 messages that are part of the dialect are re-exported here.
+//! `Tauth` and `Tattach` are this dialect's own, which carry a numeric user ID,
+//! so they have to be named through `l` when `p2000` is glob imported as well.
+
+mod errno;
+pub use errno::*;
+
+mod flags;
+pub use flags::*;
+
 pub use super::{
-    Qid, Rattach, Rauth, Rclunk, Rcreate, Rerror, Rflush, Ropen, Rread, Rremove, Rstat, Rversion,
-    Rwalk, Rwrite, Rwstat, Tclunk, Tcreate, Tflush, Topen, Tread, Tremove, Tstat, Tversion, Twalk,
-    Twrite, Twstat,
+    Qid, Rattach, Rauth, Rclunk, Rflush, Rread, Rremove, Rversion, Rwalk, Rwrite, Tclunk, Tflush,
+    Tread, Tremove, Tversion, Twalk, Twrite,
 };
+use crate::de::deserialize_owned_bytes;
+use crate::message::{ConstMessageTypeId, Payload, Protocol};
+use crate::ser::serialize_bytes;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
@@ -27,7 +41,7 @@ pub struct Tattach {
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
 pub struct Rlerror {
     pub tag: u16,
-    pub ecode: String,
+    pub ecode: Errno,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
@@ -54,13 +68,13 @@ pub struct Rstatfs {
 pub struct Tlopen {
     pub tag: u16,
     pub fid: u32,
-    pub flags: u32, // TODO
+    pub flags: OpenFlags,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
 pub struct Rlopen {
     pub tag: u16,
-    pub fid: u32,
+    pub qid: Qid,
     pub iounit: u32,
 }
 
@@ -69,8 +83,8 @@ pub struct Tlcreate {
     pub tag: u16,
     pub fid: u32,
     pub name: String,
-    pub flags: u32, // TODO
-    pub mode: u32,  // todo
+    pub flags: OpenFlags,
+    pub mode: Mode,
     pub gid: u32,
 }
 
@@ -101,7 +115,7 @@ pub struct Tmknod {
     pub tag: u16,
     pub dfid: u32,
     pub name: String,
-    pub mode: u32, // TODO
+    pub mode: Mode,
     pub major: u32,
     pub minor: u32,
     pub gid: u32,
@@ -123,7 +137,7 @@ pub struct Trename {
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
 pub struct Rrename {
-    pub tag: u32,
+    pub tag: u16,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
@@ -142,16 +156,16 @@ pub struct Rreadlink {
 pub struct Tgetattr {
     pub tag: u16,
     pub fid: u32,
-    pub request_mask: u64, // TODO
+    pub request_mask: GetattrMask,
 }
 
-// TODO: flags for all of this, perhaps temporal types?
+// TODO: perhaps temporal types?
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
 pub struct Rgetattr {
     pub tag: u16,
-    pub valid: u64,
+    pub valid: GetattrMask,
     pub qid: Qid,
-    pub mode: u32, // TODO
+    pub mode: Mode,
     pub uid: u32,
     pub gid: u32,
     pub nlink: u64,
@@ -175,12 +189,13 @@ pub struct Rgetattr {
     pub data_version: u64,
 }
 
-// TODO validity flags, chrono
+// TODO chrono
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
 pub struct Tsetattr {
     pub tag: u16,
-    pub valid: u32,
-    pub mode: u32,
+    pub fid: u32,
+    pub valid: SetattrMask,
+    pub mode: Mode,
     pub uid: u32,
     pub gid: u32,
     pub size: u64,
@@ -196,4 +211,348 @@ pub struct Rsetattr {
     pub tag: u16,
 }
 
-// TODO: the rest
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct Txattrwalk {
+    pub tag: u16,
+    pub fid: u32,
+    pub newfid: u32,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct Rxattrwalk {
+    pub tag: u16,
+    pub size: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct Txattrcreate {
+    pub tag: u16,
+    pub fid: u32,
+    pub name: String,
+    pub attr_size: u64,
+    pub flags: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct Rxattrcreate {
+    pub tag: u16,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct Treaddir {
+    pub tag: u16,
+    pub fid: u32,
+    pub offset: u64,
+    pub count: u32,
+}
+
+/// The data is a series of `Dirent`s, one after the other.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct Rreaddir {
+    pub tag: u16,
+    #[serde(
+        serialize_with = "serialize_bytes",
+        deserialize_with = "deserialize_owned_bytes"
+    )]
+    pub data: Vec<u8>,
+}
+
+/// A directory entry, as returned by `Treaddir`.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct Dirent {
+    pub qid: Qid,
+    /// The offset to read from to get the entries after this one.
+    pub offset: u64,
+    pub type_: u8,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct Tfsync {
+    pub tag: u16,
+    pub fid: u32,
+    pub datasync: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct Rfsync {
+    pub tag: u16,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct Tlock {
+    pub tag: u16,
+    pub fid: u32,
+    pub type_: u8,
+    pub flags: u32,
+    pub start: u64,
+    pub length: u64,
+    pub proc_id: u32,
+    pub client_id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct Rlock {
+    pub tag: u16,
+    pub status: u8,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct Tgetlock {
+    pub tag: u16,
+    pub fid: u32,
+    pub type_: u8,
+    pub start: u64,
+    pub length: u64,
+    pub proc_id: u32,
+    pub client_id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct Rgetlock {
+    pub tag: u16,
+    pub type_: u8,
+    pub start: u64,
+    pub length: u64,
+    pub proc_id: u32,
+    pub client_id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct Tlink {
+    pub tag: u16,
+    pub dfid: u32,
+    pub fid: u32,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct Rlink {
+    pub tag: u16,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct Tmkdir {
+    pub tag: u16,
+    pub dfid: u32,
+    pub name: String,
+    pub mode: Mode,
+    pub gid: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct Rmkdir {
+    pub tag: u16,
+    pub qid: Qid,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct Trenameat {
+    pub tag: u16,
+    pub olddirfid: u32,
+    pub oldname: String,
+    pub newdirfid: u32,
+    pub newname: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct Rrenameat {
+    pub tag: u16,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct Tunlinkat {
+    pub tag: u16,
+    pub dirfd: u32,
+    pub name: String,
+    pub flags: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct Runlinkat {
+    pub tag: u16,
+}
+
+crate::message_type_ids! {
+    Rlerror = 7,
+
+    Tstatfs = 8,
+    Rstatfs = 9,
+
+    Tlopen = 12,
+    Rlopen = 13,
+
+    Tlcreate = 14,
+    Rlcreate = 15,
+
+    Tsymlink = 16,
+    Rsymlink = 17,
+
+    Tmknod = 18,
+    Rmknod = 19,
+
+    Trename = 20,
+    Rrename = 21,
+
+    Treadlink = 22,
+    Rreadlink = 23,
+
+    Tgetattr = 24,
+    Rgetattr = 25,
+
+    Tsetattr = 26,
+    Rsetattr = 27,
+
+    Txattrwalk = 30,
+    Rxattrwalk = 31,
+
+    Txattrcreate = 32,
+    Rxattrcreate = 33,
+
+    Treaddir = 40,
+    Rreaddir = 41,
+
+    Tfsync = 50,
+    Rfsync = 51,
+
+    Tlock = 52,
+    Rlock = 53,
+
+    Tgetlock = 54,
+    Rgetlock = 55,
+
+    Tlink = 70,
+    Rlink = 71,
+
+    Tmkdir = 72,
+    Rmkdir = 73,
+
+    Trenameat = 74,
+    Rrenameat = 75,
+
+    Tunlinkat = 76,
+    Runlinkat = 77,
+
+    Tauth = 102,
+    Tattach = 104
+}
+
+crate::message_set! {
+    /// Any 9P2000.L message.
+    #[derive(Debug, PartialEq, Eq)]
+    pub enum Message {
+        Rlerror,
+
+        Tstatfs,
+        Rstatfs,
+
+        Tlopen,
+        Rlopen,
+
+        Tlcreate,
+        Rlcreate,
+
+        Tsymlink,
+        Rsymlink,
+
+        Tmknod,
+        Rmknod,
+
+        Trename,
+        Rrename,
+
+        Treadlink,
+        Rreadlink,
+
+        Tgetattr,
+        Rgetattr,
+
+        Tsetattr,
+        Rsetattr,
+
+        Txattrwalk,
+        Rxattrwalk,
+
+        Txattrcreate,
+        Rxattrcreate,
+
+        Treaddir,
+        Rreaddir,
+
+        Tfsync,
+        Rfsync,
+
+        Tlock,
+        Rlock,
+
+        Tgetlock,
+        Rgetlock,
+
+        Tlink,
+        Rlink,
+
+        Tmkdir,
+        Rmkdir,
+
+        Trenameat,
+        Rrenameat,
+
+        Tunlinkat,
+        Runlinkat,
+
+        Tversion,
+        Rversion,
+
+        Tauth,
+        Rauth,
+
+        Tattach,
+        Rattach,
+
+        Tflush,
+        Rflush,
+
+        Twalk,
+        Rwalk,
+
+        Tread,
+        Rread,
+
+        Twrite,
+        Rwrite,
+
+        Tclunk,
+        Rclunk,
+
+        Tremove,
+        Rremove,
+    }
+}
+
+/// The 9P2000.L dialect, made up of the messages in `Message`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct P2000L;
+
+impl Protocol for P2000L {
+    const VERSION: &'static str = "9P2000.L";
+    const ERROR_TYPE_ID: u8 = Rlerror::MSG_TYPE_ID;
+    type Message = Message;
+}
+
+impl Payload for Rreaddir {
+    fn payload(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl Payload for Message {
+    fn payload(&self) -> &[u8] {
+        match self {
+            Message::Rread(msg) => msg.payload(),
+            Message::Twrite(msg) => msg.payload(),
+            Message::Rreaddir(msg) => msg.payload(),
+            _ => &[],
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, ErrorKind};
+
+macro_rules! errnos {
+    ($($name:ident = $code:expr, $desc:expr;)*) => {
+        /// A Linux error number, as sent by 9P2000.L servers in `Rlerror`.
+        ///
+        /// Numbers without a variant of their own are kept in `Other`.
+        #[allow(clippy::upper_case_acronyms)]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum Errno {
+            $(
+                #[doc = $desc]
+                $name,
+            )*
+            Other(u32),
+        }
+
+        impl From<u32> for Errno {
+            fn from(code: u32) -> Self {
+                match code {
+                    $($code => Errno::$name,)*
+                    code => Errno::Other(code),
+                }
+            }
+        }
+
+        impl From<Errno> for u32 {
+            fn from(errno: Errno) -> Self {
+                match errno {
+                    $(Errno::$name => $code,)*
+                    Errno::Other(code) => code,
+                }
+            }
+        }
+
+        impl Errno {
+            /// The symbolic name of the error, such as `"ENOENT"`.
+            pub fn name(self) -> Option<&'static str> {
+                match self {
+                    $(Errno::$name => Some(stringify!($name)),)*
+                    Errno::Other(_) => None,
+                }
+            }
+
+            /// A description of the error, as given by `strerror`.
+            pub fn message(self) -> Option<&'static str> {
+                match self {
+                    $(Errno::$name => Some($desc),)*
+                    Errno::Other(_) => None,
+                }
+            }
+        }
+    };
+}
+
+errnos! {
+    EPERM = 1, "Operation not permitted";
+    ENOENT = 2, "No such file or directory";
+    ESRCH = 3, "No such process";
+    EINTR = 4, "Interrupted system call";
+    EIO = 5, "Input/output error";
+    ENXIO = 6, "No such device or address";
+    E2BIG = 7, "Argument list too long";
+    ENOEXEC = 8, "Exec format error";
+    EBADF = 9, "Bad file descriptor";
+    ECHILD = 10, "No child processes";
+    EAGAIN = 11, "Resource temporarily unavailable";
+    ENOMEM = 12, "Cannot allocate memory";
+    EACCES = 13, "Permission denied";
+    EFAULT = 14, "Bad address";
+    ENOTBLK = 15, "Block device required";
+    EBUSY = 16, "Device or resource busy";
+    EEXIST = 17, "File exists";
+    EXDEV = 18, "Invalid cross-device link";
+    ENODEV = 19, "No such device";
+    ENOTDIR = 20, "Not a directory";
+    EISDIR = 21, "Is a directory";
+    EINVAL = 22, "Invalid argument";
+    ENFILE = 23, "Too many open files in system";
+    EMFILE = 24, "Too many open files";
+    ENOTTY = 25, "Inappropriate ioctl for device";
+    ETXTBSY = 26, "Text file busy";
+    EFBIG = 27, "File too large";
+    ENOSPC = 28, "No space left on device";
+    ESPIPE = 29, "Illegal seek";
+    EROFS = 30, "Read-only file system";
+    EMLINK = 31, "Too many links";
+    EPIPE = 32, "Broken pipe";
+    EDOM = 33, "Numerical argument out of domain";
+    ERANGE = 34, "Numerical result out of range";
+    EDEADLK = 35, "Resource deadlock avoided";
+    ENAMETOOLONG = 36, "File name too long";
+    ENOLCK = 37, "No locks available";
+    ENOSYS = 38, "Function not implemented";
+    ENOTEMPTY = 39, "Directory not empty";
+    ELOOP = 40, "Too many levels of symbolic links";
+    ENODATA = 61, "No data available";
+    EPROTO = 71, "Protocol error";
+    EOVERFLOW = 75, "Value too large for defined data type";
+    EOPNOTSUPP = 95, "Operation not supported";
+    EADDRINUSE = 98, "Address already in use";
+    EADDRNOTAVAIL = 99, "Cannot assign requested address";
+    ECONNABORTED = 103, "Software caused connection abort";
+    ECONNRESET = 104, "Connection reset by peer";
+    ENOTCONN = 107, "Transport endpoint is not connected";
+    ETIMEDOUT = 110, "Connection timed out";
+    ECONNREFUSED = 111, "Connection refused";
+    ESTALE = 116, "Stale file handle";
+    EDQUOT = 122, "Disk quota exceeded";
+}
+
+impl Display for Errno {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.message() {
+            Some(message) => f.write_str(message),
+            None => write!(f, "Unknown error {}", u32::from(*self)),
+        }
+    }
+}
+
+impl Error for Errno {}
+
+impl Serialize for Errno {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u32((*self).into())
+    }
+}
+
+impl<'de> Deserialize<'de> for Errno {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        u32::deserialize(deserializer).map(Errno::from)
+    }
+}
+
+impl From<Errno> for ErrorKind {
+    fn from(errno: Errno) -> Self {
+        match errno {
+            Errno::EPERM | Errno::EACCES => ErrorKind::PermissionDenied,
+            Errno::ENOENT => ErrorKind::NotFound,
+            Errno::EINTR => ErrorKind::Interrupted,
+            Errno::EAGAIN => ErrorKind::WouldBlock,
+            Errno::ENOMEM => ErrorKind::OutOfMemory,
+            Errno::EEXIST => ErrorKind::AlreadyExists,
+            Errno::EINVAL => ErrorKind::InvalidInput,
+            Errno::EPIPE => ErrorKind::BrokenPipe,
+            Errno::ENOSYS | Errno::EOPNOTSUPP => ErrorKind::Unsupported,
+            Errno::EADDRINUSE => ErrorKind::AddrInUse,
+            Errno::EADDRNOTAVAIL => ErrorKind::AddrNotAvailable,
+            Errno::ECONNABORTED => ErrorKind::ConnectionAborted,
+            Errno::ECONNRESET => ErrorKind::ConnectionReset,
+            Errno::ENOTCONN => ErrorKind::NotConnected,
+            Errno::ETIMEDOUT => ErrorKind::TimedOut,
+            Errno::ECONNREFUSED => ErrorKind::ConnectionRefused,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+/// The closest error number for the given kind of error, `EIO` if there's none.
+impl From<ErrorKind> for Errno {
+    fn from(kind: ErrorKind) -> Self {
+        match kind {
+            ErrorKind::PermissionDenied => Errno::EACCES,
+            ErrorKind::NotFound => Errno::ENOENT,
+            ErrorKind::Interrupted => Errno::EINTR,
+            ErrorKind::WouldBlock => Errno::EAGAIN,
+            ErrorKind::OutOfMemory => Errno::ENOMEM,
+            ErrorKind::AlreadyExists => Errno::EEXIST,
+            ErrorKind::InvalidInput => Errno::EINVAL,
+            ErrorKind::BrokenPipe => Errno::EPIPE,
+            ErrorKind::Unsupported => Errno::EOPNOTSUPP,
+            ErrorKind::AddrInUse => Errno::EADDRINUSE,
+            ErrorKind::AddrNotAvailable => Errno::EADDRNOTAVAIL,
+            ErrorKind::ConnectionAborted => Errno::ECONNABORTED,
+            ErrorKind::ConnectionReset => Errno::ECONNRESET,
+            ErrorKind::NotConnected => Errno::ENOTCONN,
+            ErrorKind::TimedOut => Errno::ETIMEDOUT,
+            ErrorKind::ConnectionRefused => Errno::ECONNREFUSED,
+            _ => Errno::EIO,
+        }
+    }
+}
+
+/// On Linux, this is the OS error with the same number.
+impl From<Errno> for io::Error {
+    fn from(errno: Errno) -> Self {
+        if cfg!(target_os = "linux") {
+            io::Error::from_raw_os_error(u32::from(errno) as i32)
+        } else {
+            io::Error::new(errno.into(), errno)
+        }
+    }
+}
+
+/// Linux OS errors keep their number, others get the closest one for their kind.
+impl From<&io::Error> for Errno {
+    fn from(err: &io::Error) -> Self {
+        if let Some(&errno) = err
+            .get_ref()
+            .and_then(|inner| inner.downcast_ref::<Errno>())
+        {
+            return errno;
+        }
+        match err.raw_os_error() {
+            Some(code) if cfg!(target_os = "linux") => Errno::from(code as u32),
+            _ => err.kind().into(),
+        }
+    }
+}
+
+impl From<io::Error> for Errno {
+    fn from(err: io::Error) -> Self {
+        Errno::from(&err)
+    }
+}
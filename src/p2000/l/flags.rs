@@ -0,0 +1,252 @@
+use bitflags::bitflags;
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+#[cfg(unix)]
+use std::fs::{Metadata, Permissions};
+#[cfg(unix)]
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+bitflags! {
+    /// The flags a file is opened or created with, as in `Tlopen` and `Tlcreate`.
+    ///
+    /// The values are Linux's generic `O_*` flags. The access mode is a single value
+    /// in the `ACCESS_MASK` bits rather than a set of flags, so it's read with `access`
+    /// and given with `Access`, such as `OpenFlags::from(Access::ReadWrite) | OpenFlags::CREATE`.
+    #[derive(Serialize, Deserialize)]
+    pub struct OpenFlags: u32 {
+        const CREATE    = 0o100;
+        const EXCL      = 0o200;
+        const NOCTTY    = 0o400;
+        const TRUNC     = 0o1000;
+        const APPEND    = 0o2000;
+        const NONBLOCK  = 0o4000;
+        const DSYNC     = 0o10000;
+        const FASYNC    = 0o20000;
+        const DIRECT    = 0o40000;
+        const LARGEFILE = 0o100000;
+        const DIRECTORY = 0o200000;
+        const NOFOLLOW  = 0o400000;
+        const NOATIME   = 0o1000000;
+        const CLOEXEC   = 0o2000000;
+        const SYNC      = 0o4000000;
+
+        const ACCESS_MASK = 3;
+    }
+}
+
+impl OpenFlags {
+    /// The access mode of the flags.
+    pub fn access(&self) -> Access {
+        match self.bits() & OpenFlags::ACCESS_MASK.bits() {
+            0 => Access::ReadOnly,
+            1 => Access::WriteOnly,
+            2 => Access::ReadWrite,
+            _ => Access::NoAccess,
+        }
+    }
+
+    /// Whether or not the flags mean a file is
+    /// (or is requested to be) readable.
+    pub fn is_readable(&self) -> bool {
+        match self.access() {
+            Access::ReadOnly | Access::ReadWrite => true,
+            Access::WriteOnly | Access::NoAccess => false,
+        }
+    }
+
+    /// Whether or not the flags mean a file is
+    /// (or is requested to be) writable.
+    pub fn is_writable(&self) -> bool {
+        match self.access() {
+            Access::WriteOnly | Access::ReadWrite => true,
+            Access::ReadOnly | Access::NoAccess => false,
+        }
+    }
+}
+
+/// The access mode a file is opened with, as given by the `O_ACCMODE` bits of its flags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Access {
+    ReadOnly,
+    WriteOnly,
+    ReadWrite,
+    /// Neither reading nor writing, as used by Linux to open a file only for ioctls.
+    NoAccess,
+}
+
+/// Flags with the given access mode and nothing else.
+impl From<Access> for OpenFlags {
+    fn from(access: Access) -> Self {
+        let bits = match access {
+            Access::ReadOnly => 0,
+            Access::WriteOnly => 1,
+            Access::ReadWrite => 2,
+            Access::NoAccess => 3,
+        };
+        OpenFlags::from_bits_truncate(bits)
+    }
+}
+
+/// Options to open a file with the same access and creation flags.
+/// The other flags have no portable equivalent, so they're left out.
+impl From<OpenFlags> for OpenOptions {
+    fn from(flags: OpenFlags) -> Self {
+        let mut options = OpenOptions::new();
+        options
+            .read(flags.is_readable())
+            .write(flags.is_writable())
+            .append(flags.contains(OpenFlags::APPEND))
+            .truncate(flags.contains(OpenFlags::TRUNC))
+            .create(flags.contains(OpenFlags::CREATE))
+            .create_new(flags.contains(OpenFlags::CREATE | OpenFlags::EXCL));
+        options
+    }
+}
+
+bitflags! {
+    /// The mode of a file, representing its type as well as permissions.
+    ///
+    /// The values are those of `st_mode`. The type is a single value in the
+    /// `S_IF*` bits rather than a set of flags, so it's read with `file_type`
+    /// and given with `FileKind`, such as `Mode::from(FileKind::Dir) | Mode::OWNER_READ`.
+    #[derive(Serialize, Deserialize)]
+    pub struct Mode: u32 {
+        const SETUID = 0o4000;
+        const SETGID = 0o2000;
+        const STICKY = 0o1000;
+
+        const OWNER_READ  = 0o400;
+        const OWNER_WRITE = 0o200;
+        const OWNER_EXEC  = 0o100;
+
+        const GROUP_READ  = 0o40;
+        const GROUP_WRITE = 0o20;
+        const GROUP_EXEC  = 0o10;
+
+        const OTHER_READ  = 0o4;
+        const OTHER_WRITE = 0o2;
+        const OTHER_EXEC  = 0o1;
+
+        const TYPE_MASK = 0o170000;
+        const PERM_MASK = 0o777;
+    }
+}
+
+impl Mode {
+    /// The type of the file, or `None` if the type bits don't name one.
+    pub fn file_type(&self) -> Option<FileKind> {
+        FileKind::from_bits(self.bits())
+    }
+}
+
+/// The type of a file, as given by the `S_IF*` bits of its mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FileKind {
+    Socket,
+    Symlink,
+    Regular,
+    Block,
+    Dir,
+    Char,
+    Fifo,
+}
+
+impl FileKind {
+    /// The `S_IF*` value of the type.
+    pub fn bits(self) -> u32 {
+        match self {
+            FileKind::Socket => 0o140000,
+            FileKind::Symlink => 0o120000,
+            FileKind::Regular => 0o100000,
+            FileKind::Block => 0o060000,
+            FileKind::Dir => 0o040000,
+            FileKind::Char => 0o020000,
+            FileKind::Fifo => 0o010000,
+        }
+    }
+
+    /// The type named by the type bits of the given `st_mode` value, if any.
+    pub fn from_bits(mode: u32) -> Option<FileKind> {
+        match mode & Mode::TYPE_MASK.bits() {
+            0o140000 => Some(FileKind::Socket),
+            0o120000 => Some(FileKind::Symlink),
+            0o100000 => Some(FileKind::Regular),
+            0o060000 => Some(FileKind::Block),
+            0o040000 => Some(FileKind::Dir),
+            0o020000 => Some(FileKind::Char),
+            0o010000 => Some(FileKind::Fifo),
+            _ => None,
+        }
+    }
+}
+
+/// A mode of the given type with no permissions.
+impl From<FileKind> for Mode {
+    fn from(kind: FileKind) -> Self {
+        Mode::from_bits_truncate(kind.bits())
+    }
+}
+
+#[cfg(unix)]
+impl From<Mode> for Permissions {
+    fn from(mode: Mode) -> Self {
+        Permissions::from_mode((mode - Mode::TYPE_MASK).bits())
+    }
+}
+
+#[cfg(unix)]
+impl From<Permissions> for Mode {
+    fn from(perms: Permissions) -> Self {
+        Mode::from_bits_truncate(perms.mode())
+    }
+}
+
+#[cfg(unix)]
+impl From<&Metadata> for Mode {
+    fn from(metadata: &Metadata) -> Self {
+        Mode::from_bits_truncate(metadata.mode())
+    }
+}
+
+bitflags! {
+    /// The attributes asked for in `Tgetattr`, and those that are valid in `Rgetattr`.
+    #[derive(Serialize, Deserialize)]
+    pub struct GetattrMask: u64 {
+        const MODE         = 0x0000_0001;
+        const NLINK        = 0x0000_0002;
+        const UID          = 0x0000_0004;
+        const GID          = 0x0000_0008;
+        const RDEV         = 0x0000_0010;
+        const ATIME        = 0x0000_0020;
+        const MTIME        = 0x0000_0040;
+        const CTIME        = 0x0000_0080;
+        const INO          = 0x0000_0100;
+        const SIZE         = 0x0000_0200;
+        const BLOCKS       = 0x0000_0400;
+        const BTIME        = 0x0000_0800;
+        const GEN          = 0x0000_1000;
+        const DATA_VERSION = 0x0000_2000;
+
+        /// Everything `stat` gives.
+        const BASIC = 0x0000_07ff;
+        const ALL   = 0x0000_3fff;
+    }
+}
+
+bitflags! {
+    /// The attributes to be set by `Tsetattr`.
+    ///
+    /// Without `ATIME_SET` or `MTIME_SET`, the times are set to the server's current time.
+    #[derive(Serialize, Deserialize)]
+    pub struct SetattrMask: u32 {
+        const MODE      = 0x0000_0001;
+        const UID       = 0x0000_0002;
+        const GID       = 0x0000_0004;
+        const SIZE      = 0x0000_0008;
+        const ATIME     = 0x0000_0010;
+        const MTIME     = 0x0000_0020;
+        const CTIME     = 0x0000_0040;
+        const ATIME_SET = 0x0000_0080;
+        const MTIME_SET = 0x0000_0100;
+    }
+}
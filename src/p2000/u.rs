@@ -1,10 +1,93 @@
+//! The messages of 9P2000.u, the Unix extension of 9p, along with the openfd extension.
+//!
+//! Only the 9P2000 messages that are unchanged in the dialect are re-exported here.
+//! The ones this dialect extends with Unix details are its own,
+//! so they have to be named through `u` when `p2000` is glob imported as well.
+
+pub use super::l::Errno;
 pub use super::{
-    OpenMode, Qid, Rattach, Rauth, Rclunk, Rcreate, Rerror, Rflush, Ropen, Rread, Rremove, Rstat,
-    Rversion, Rwalk, Rwrite, Rwstat, Tattach, Tauth, Tclunk, Tcreate, Tflush, Topen, Tread,
-    Tremove, Tstat, Tversion, Twalk, Twrite, Twstat,
+    FileMode, FileType, OpenMode, Qid, Rattach, Rauth, Rclunk, Rcreate, Rflush, Ropen, Rread,
+    Rremove, Rversion, Rwalk, Rwrite, Rwstat, Tclunk, Tflush, Topen, Tread, Tremove, Tstat,
+    Tversion, Twalk, Twrite,
 };
+use crate::message::{ConstMessageTypeId, Payload, Protocol};
 use serde::{Deserialize, Serialize};
 
+/// The metadata of a file in 9P2000.u, which adds Unix details to the 9P2000 stat.
+///
+/// Like the 9P2000 stat, it's sent with its size in front.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct Stat {
+    pub type_: u16,
+    pub dev: u32,
+    pub qid: Qid,
+    pub mode: FileMode,
+    pub atime: u32,
+    pub mtime: u32,
+    pub length: u64,
+    pub name: String,
+    pub uid: String,
+    pub gid: String,
+    pub muid: String,
+    /// The target of a symlink, or a description of a device file, like `"c 1 3"`.
+    pub extension: String,
+    pub n_uid: u32,
+    pub n_gid: u32,
+    pub n_muid: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct Tauth {
+    pub tag: u16,
+    pub afid: u32,
+    pub uname: String,
+    pub aname: String,
+    pub n_uname: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct Tattach {
+    pub tag: u16,
+    pub fid: u32,
+    pub afid: u32,
+    pub uname: String,
+    pub aname: String,
+    pub n_uname: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct Rerror {
+    pub tag: u16,
+    pub ename: String,
+    pub errno: Errno,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct Tcreate {
+    pub tag: u16,
+    pub fid: u32,
+    pub name: String,
+    pub perm: FileMode,
+    pub mode: OpenMode,
+    /// The target of a symlink, or a description of a device file, like `"c 1 3"`.
+    pub extension: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct Rstat {
+    pub tag: u16,
+    #[serde(with = "crate::message::double_size_prefixed")]
+    pub stat: Stat,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct Twstat {
+    pub tag: u16,
+    pub fid: u32,
+    #[serde(with = "crate::message::double_size_prefixed")]
+    pub stat: Stat,
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
 pub struct Topenfd {
     pub tag: u16,
@@ -22,5 +105,82 @@ pub struct Ropenfd {
 
 crate::message_type_ids! {
     Topenfd = 98,
-    Ropenfd = 99
+    Ropenfd = 99,
+
+    Tauth = 102,
+    Tattach = 104,
+    Rerror = 107,
+    Tcreate = 114,
+    Rstat = 125,
+    Twstat = 126
+}
+
+crate::message_set! {
+    /// Any 9P2000.u message.
+    #[derive(Debug, PartialEq, Eq)]
+    pub enum Message {
+        Topenfd,
+        Ropenfd,
+
+        Tversion,
+        Rversion,
+
+        Tauth,
+        Rauth,
+
+        Tattach,
+        Rattach,
+
+        Rerror,
+
+        Tflush,
+        Rflush,
+
+        Twalk,
+        Rwalk,
+
+        Topen,
+        Ropen,
+
+        Tcreate,
+        Rcreate,
+
+        Tread,
+        Rread,
+
+        Twrite,
+        Rwrite,
+
+        Tclunk,
+        Rclunk,
+
+        Tremove,
+        Rremove,
+
+        Tstat,
+        Rstat,
+
+        Twstat,
+        Rwstat,
+    }
+}
+
+/// The 9P2000.u dialect, made up of the messages in `Message`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct P2000U;
+
+impl Protocol for P2000U {
+    const VERSION: &'static str = "9P2000.u";
+    const ERROR_TYPE_ID: u8 = Rerror::MSG_TYPE_ID;
+    type Message = Message;
+}
+
+impl Payload for Message {
+    fn payload(&self) -> &[u8] {
+        match self {
+            Message::Rread(msg) => msg.payload(),
+            Message::Twrite(msg) => msg.payload(),
+            _ => &[],
+        }
+    }
 }
@@ -0,0 +1,296 @@
+//! `registry` lets messages outside of a dialect's message set be sent and received,
+//! such as private message types added for an experiment.
+//!
+//! Decoding frames into an `Extended` message set never fails because of an
+//! unknown type ID: such messages are kept as their raw body instead.
+//! A `Registry` of custom message types, built at runtime, can then decode those
+//! into trait objects.
+//! ```
+//! # use nine::de::*;
+//! # use nine::ser::*;
+//! use nine::p2000::Message;
+//! use nine::registry::{Extended, Registry};
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize, Debug, PartialEq)]
+//! struct Tsync {
+//!     tag: u16,
+//!     fid: u32,
+//! }
+//! nine::message_type_ids! { Tsync = 150 }
+//!
+//! let frame = into_frame(&Tsync { tag: 1, fid: 2 }).unwrap();
+//! let msg: Extended<Message> = from_frame(&frame).unwrap();
+//! assert!(matches!(msg, Extended::Unknown(_)));
+//!
+//! let mut registry = Registry::new();
+//! registry.register::<Tsync>();
+//! match registry.resolve(msg).unwrap() {
+//!     Extended::Custom(custom) => {
+//!         assert_eq!(custom.downcast_ref(), Some(&Tsync { tag: 1, fid: 2 }));
+//!     }
+//!     _ => panic!(),
+//! }
+//! ```
+
+use crate::de::*;
+use crate::message::{ConstMessageTypeId, MessageSet, MessageTypeId, RawBytes, RawSlice};
+use crate::ser::*;
+use serde::de::DeserializeOwned;
+use serde::ser::Error;
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Formatter};
+use std::io::Read;
+
+/// A message that can be sent and received as a custom message type.
+///
+/// Implemented for every message type with a constant type ID.
+pub trait CustomMessage: Any + Debug + Send + Sync {
+    /// The message's type ID.
+    fn custom_type_id(&self) -> u8;
+
+    /// Serialize the body of the message, without its type ID.
+    fn to_body(&self) -> Result<Vec<u8>, SerError>;
+
+    /// The message as `Any`, so that it can be downcast to its type.
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<T> CustomMessage for T
+where
+    T: ConstMessageTypeId + Serialize + Debug + Send + Sync + 'static,
+{
+    fn custom_type_id(&self) -> u8 {
+        T::MSG_TYPE_ID
+    }
+
+    fn to_body(&self) -> Result<Vec<u8>, SerError> {
+        into_bytes(self)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl dyn CustomMessage {
+    /// The message as the given type, if that's what it is.
+    pub fn downcast_ref<T: CustomMessage>(&self) -> Option<&T> {
+        self.as_any().downcast_ref()
+    }
+}
+
+/// A message whose type isn't known, kept as its type ID and raw body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawMessage {
+    pub msg_type: u8,
+    pub body: Vec<u8>,
+}
+
+/// A message set extended with messages outside of it.
+///
+/// As a `MessageSet`, it holds every message that isn't in the base set
+/// as a `RawMessage`, so it can be used wherever a message set can,
+/// such as `read_message` or a `FrameDecoder`.
+#[derive(Debug)]
+pub enum Extended<M> {
+    /// A message in the base set.
+    Known(M),
+    /// A custom message, as decoded by a `Registry` or made to be sent.
+    Custom(Box<dyn CustomMessage>),
+    /// A message whose type isn't known.
+    Unknown(RawMessage),
+}
+
+impl<M> Extended<M> {
+    /// Wrap the given custom message to be sent.
+    pub fn custom<T: CustomMessage>(msg: T) -> Self {
+        Extended::Custom(Box::new(msg))
+    }
+}
+
+impl<M: MessageSet> MessageSet for Extended<M> {
+    const MSG_TYPE_IDS: &'static [u8] = M::MSG_TYPE_IDS;
+
+    fn deserialize_body<'de, D: Deserializer<'de>>(
+        msg_type: u8,
+        deserializer: D,
+    ) -> Result<Option<Self>, D::Error> {
+        if M::MSG_TYPE_IDS.contains(&msg_type) {
+            return M::deserialize_body(msg_type, deserializer).map(|msg| msg.map(Extended::Known));
+        }
+        let RawBytes(body) = RawBytes::deserialize(deserializer)?;
+        Ok(Some(Extended::Unknown(RawMessage { msg_type, body })))
+    }
+}
+
+impl<M: MessageTypeId> MessageTypeId for Extended<M> {
+    fn msg_type_id(&self) -> u8 {
+        match self {
+            Extended::Known(msg) => msg.msg_type_id(),
+            Extended::Custom(msg) => msg.custom_type_id(),
+            Extended::Unknown(raw) => raw.msg_type,
+        }
+    }
+
+    fn serializes_type_id(&self) -> bool {
+        true
+    }
+}
+
+impl<M: Serialize + MessageTypeId> Serialize for Extended<M> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let msg_type = self.msg_type_id() as u32;
+        match self {
+            Extended::Known(msg) if msg.serializes_type_id() => msg.serialize(serializer),
+            Extended::Known(msg) => {
+                serializer.serialize_newtype_variant("Extended", msg_type, "Known", msg)
+            }
+            Extended::Custom(msg) => {
+                let body = msg.to_body().map_err(S::Error::custom)?;
+                serializer.serialize_newtype_variant(
+                    "Extended",
+                    msg_type,
+                    "Custom",
+                    &RawSlice(&body),
+                )
+            }
+            Extended::Unknown(raw) => serializer.serialize_newtype_variant(
+                "Extended",
+                msg_type,
+                "Unknown",
+                &RawSlice(&raw.body),
+            ),
+        }
+    }
+}
+
+type Decode = fn(&[u8], &DecoderConfig) -> Result<Box<dyn CustomMessage>, DeError>;
+
+fn decode<T: CustomMessage + DeserializeOwned>(
+    body: &[u8],
+    config: &DecoderConfig,
+) -> Result<Box<dyn CustomMessage>, DeError> {
+    let mut des = config.configure(SliceDeserializer::new(body));
+    let msg = T::deserialize(&mut des)?;
+    if config.strict && !des.inner.input.is_empty() {
+        return Err(DeError::TrailingBytes(des.inner.input.len() as u64));
+    }
+    Ok(Box::new(msg))
+}
+
+/// Custom message types, by type ID, for decoding the raw messages of an `Extended` message set.
+#[derive(Default)]
+pub struct Registry {
+    decoders: HashMap<u8, Decode>,
+}
+
+impl Debug for Registry {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let mut ids: Vec<_> = self.decoders.keys().collect();
+        ids.sort();
+        f.debug_struct("Registry").field("type_ids", &ids).finish()
+    }
+}
+
+impl Registry {
+    /// Create a registry with no custom message types.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register the given message type under its type ID, replacing
+    /// whatever was registered under it before.
+    ///
+    /// Messages of the base set are never raw, so type IDs in it are never looked up.
+    pub fn register<T>(&mut self) -> &mut Self
+    where
+        T: ConstMessageTypeId + CustomMessage + DeserializeOwned,
+    {
+        self.decoders.insert(T::MSG_TYPE_ID, decode::<T>);
+        self
+    }
+
+    /// Whether a message type is registered under the given type ID.
+    pub fn contains(&self, msg_type: u8) -> bool {
+        self.decoders.contains_key(&msg_type)
+    }
+
+    /// Decode the given raw message with the message type registered
+    /// under its type ID, or give `None` if there isn't one.
+    pub fn decode(&self, raw: &RawMessage) -> Option<Result<Box<dyn CustomMessage>, DeError>> {
+        self.decode_with(raw, &DecoderConfig::default())
+    }
+
+    /// Decode the given raw message like `decode`, using the given configuration.
+    pub fn decode_with(
+        &self,
+        raw: &RawMessage,
+        config: &DecoderConfig,
+    ) -> Option<Result<Box<dyn CustomMessage>, DeError>> {
+        self.decoders
+            .get(&raw.msg_type)
+            .map(|decode| decode(&raw.body, config).map_err(|err| err.in_frame(raw.msg_type)))
+    }
+
+    /// Decode the given message if it's raw and its type is registered.
+    /// Otherwise, it's given back as it was.
+    pub fn resolve<M>(&self, msg: Extended<M>) -> Result<Extended<M>, DeError> {
+        self.resolve_with(msg, &DecoderConfig::default())
+    }
+
+    /// Decode the given message like `resolve`, using the given configuration.
+    pub fn resolve_with<M>(
+        &self,
+        msg: Extended<M>,
+        config: &DecoderConfig,
+    ) -> Result<Extended<M>, DeError> {
+        match msg {
+            Extended::Unknown(raw) => match self.decode_with(&raw, config) {
+                Some(res) => res.map(Extended::Custom),
+                None => Ok(Extended::Unknown(raw)),
+            },
+            msg => Ok(msg),
+        }
+    }
+
+    /// Read a whole message frame like `read_message`, decoding it as a custom message
+    /// if its type is registered.
+    pub fn read_message<M: MessageSet, R: Read>(&self, reader: R) -> Result<Extended<M>, DeError> {
+        self.read_message_with(reader, &DecoderConfig::default())
+    }
+
+    /// Read a whole message frame like `read_message_with`, decoding it as a custom message
+    /// if its type is registered.
+    ///
+    /// The configuration applies to the custom message as well.
+    pub fn read_message_with<M: MessageSet, R: Read>(
+        &self,
+        reader: R,
+        config: &DecoderConfig,
+    ) -> Result<Extended<M>, DeError> {
+        self.resolve_with(read_message_with(reader, config)?, config)
+    }
+
+    /// Deserialize a message from a byte slice holding exactly one frame like `from_frame`,
+    /// decoding it as a custom message if its type is registered.
+    pub fn from_frame<M: MessageSet, B: AsRef<[u8]>>(
+        &self,
+        bytes: B,
+    ) -> Result<Extended<M>, DeError> {
+        self.from_frame_with(bytes, &DecoderConfig::default())
+    }
+
+    /// Deserialize a message from a byte slice holding exactly one frame like
+    /// `from_frame_with`, decoding it as a custom message if its type is registered.
+    ///
+    /// The configuration applies to the custom message as well.
+    pub fn from_frame_with<M: MessageSet, B: AsRef<[u8]>>(
+        &self,
+        bytes: B,
+        config: &DecoderConfig,
+    ) -> Result<Extended<M>, DeError> {
+        self.resolve_with(from_frame_with(bytes, config)?, config)
+    }
+}
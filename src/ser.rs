@@ -1,4 +1,18 @@
 //! Serializers and serializer convenience functions.
+//!
+//! # Extended encodings
+//!
+//! 9p has no options, maps or chars, so serializing them fails by default.
+//! To experiment with the protocol, `WriteSerializer::extended` and
+//! `BytesSerializer::extended` give serializers that encode them anyway:
+//!
+//! - an `Option` as a presence byte, 0 or 1, followed by its value if present
+//! - a map like a sequence, as a two-byte count followed by each key and its value
+//! - a `char` as its UTF-8 encoding
+//!
+//! The frame functions use them when `EncoderConfig::extended` is set.
+//! A `bool` is always a single 0 or 1 byte.
+//! The deserializers have a matching `extended` mode.
 
 use std::io::{Cursor, Seek, SeekFrom};
 
@@ -11,6 +25,18 @@ pub use write_seek::*;
 mod count;
 pub use count::*;
 
+mod bytes;
+pub use self::bytes::*;
+
+mod stream;
+pub use stream::*;
+
+mod vectored;
+pub use vectored::*;
+
+mod frame;
+pub use frame::*;
+
 //region Functions
 // TODO: this is mainly used for converting stat calls.
 // It should take a ref to the writer, which would be the existing buffer on the dir.
@@ -23,10 +49,7 @@ pub use count::*;
 /// let bytes: Vec<u8> = into_bytes(&version).unwrap();
 /// ```
 pub fn into_bytes<T: Serialize>(t: &T) -> Result<Vec<u8>, SerError> {
-    let mut ser = WriteSerializer {
-        writer: Cursor::new(Vec::new()),
-        in_stat: false,
-    };
+    let mut ser = WriteSerializer::new(Cursor::new(Vec::new()));
 
     let res = t.serialize(&mut ser);
 
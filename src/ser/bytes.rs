@@ -1,151 +1,281 @@
-use super::*;
+use super::common::*;
 use ::bytes::{BufMut, BytesMut};
+use serde::ser::*;
+use std::mem;
 
+/// Serialize the given object into a new `BytesMut` buffer.
+/// ```
+/// # use nine::ser::*;
+/// use nine::p2000::Rerror;
+/// let err = Rerror { tag: 0, ename: "foo".into() };
+/// let bytes = into_bytes_mut(&err).unwrap();
+/// assert_eq!(bytes.len(), 7);
+/// ```
+pub fn into_bytes_mut<T: Serialize>(t: &T) -> Result<BytesMut, SerError> {
+    let mut buf = BytesMut::new();
+    append_bytes_mut(t, &mut buf)?;
+    Ok(buf)
+}
+
+/// Serializes the given item at the _end_ of the given `BytesMut`,
+/// growing it as needed.
+///
+/// Returns the number of bytes written.
+/// If serialization fails, the buffer is left the way it was.
+/// ```
+/// # use nine::ser::*;
+/// use bytes::BytesMut;
+/// use nine::p2000::Rerror;
+/// let mut buf = BytesMut::with_capacity(64);
+/// let err = Rerror { tag: 0, ename: "foo".into() };
+/// let amount = append_bytes_mut(&err, &mut buf).unwrap();
+/// ```
+pub fn append_bytes_mut<T: Serialize>(t: &T, buf: &mut BytesMut) -> Result<u32, SerError> {
+    let start = buf.len();
+    let mut ser = BytesSerializer::new(buf);
+
+    let res = t.serialize(&mut ser);
+    if res.is_err() {
+        buf.truncate(start);
+    }
+    res
+}
+
+/// A serializer that appends to a `BytesMut`.
+///
+/// Since the buffer is contiguous, size prefixes are written as placeholders
+/// and patched in place once their contents are known.
 #[derive(Debug)]
-struct BytesSerializer {
-    buf: BytesMut,
-    in_stat: bool, // if in a rstat/twstat message, double-prefix the size
+pub struct BytesSerializer<'b> {
+    buf: &'b mut BytesMut,
+    /// If set, the contents of byte arrays are left out, and this counts them.
+    elided: Option<u32>,
+    /// Where the buffer ended after the last byte array was left out.
+    elided_end: usize,
+    extended: bool,
+    /// Whether the next byte array is written raw, with no length prefix,
+    /// as it is directly inside a `RAW_BYTES` newtype struct.
+    raw: bool,
 }
 
-impl Serializer for BytesSerializer {
-    type Ok = BytesMut;
+impl<'b> BytesSerializer<'b> {
+    /// Create a serializer that appends to the given buffer.
+    pub fn new(buf: &'b mut BytesMut) -> BytesSerializer<'b> {
+        BytesSerializer {
+            buf,
+            elided: None,
+            elided_end: 0,
+            extended: false,
+            raw: false,
+        }
+    }
+
+    /// Write the [extended encodings](crate::ser#extended-encodings).
+    pub fn extended(self) -> BytesSerializer<'b> {
+        BytesSerializer {
+            extended: true,
+            ..self
+        }
+    }
+
+    /// Create a serializer that appends to the given buffer,
+    /// leaving out the contents of byte arrays but not their length prefixes.
+    /// The byte counts returned still include the contents.
+    pub(crate) fn eliding_bytes(buf: &'b mut BytesMut) -> BytesSerializer<'b> {
+        BytesSerializer {
+            elided: Some(0),
+            ..BytesSerializer::new(buf)
+        }
+    }
+
+    /// How many bytes of byte array contents were left out, and where the
+    /// buffer ended after the last of them, if this serializer leaves them out.
+    pub(crate) fn elided(&self) -> Option<(u32, usize)> {
+        self.elided.map(|amount| (amount, self.elided_end))
+    }
+
+    /// Overwrite a previously written `u16` at the given position.
+    fn patch_u16(&mut self, pos: usize, v: u16) {
+        self.buf[pos..pos + 2].copy_from_slice(&v.to_le_bytes());
+    }
+}
+
+impl<'ser, 'b> Serializer for &'ser mut BytesSerializer<'b> {
+    type Ok = u32;
     type Error = SerError;
 
-    type SerializeSeq = CountingSequenceSerializer;
-    type SerializeTuple = Unimplemented;
-    type SerializeTupleStruct = Unimplemented;
-    type SerializeTupleVariant = Unimplemented;
-    type SerializeMap = Unimplemented;
-    type SerializeStruct = Unimplemented;
-    type SerializeStructVariant = Unimplemented;
+    type SerializeSeq = BytesSequenceSerializer<'ser, 'b>;
+    type SerializeTuple = BytesStructSerializer<'ser, 'b>;
+    type SerializeTupleStruct = BytesStructSerializer<'ser, 'b>;
+    type SerializeTupleVariant = BytesStructSerializer<'ser, 'b>;
+    type SerializeMap = BytesSequenceSerializer<'ser, 'b>;
+    type SerializeStruct = BytesStructSerializer<'ser, 'b>;
+    type SerializeStructVariant = BytesStructSerializer<'ser, 'b>;
 
     fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
         self.buf.put_u8(v as u8);
-        Ok(self.buf)
+        Ok(1)
     }
     fn serialize_i8(self, v: i8) -> Result<Self::Ok, Self::Error> {
         self.buf.put_i8(v);
-        Ok(self.buf)
+        Ok(1)
     }
     fn serialize_i16(self, v: i16) -> Result<Self::Ok, Self::Error> {
         self.buf.put_i16_le(v);
-        Ok(self.buf)
+        Ok(2)
     }
     fn serialize_i32(self, v: i32) -> Result<Self::Ok, Self::Error> {
         self.buf.put_i32_le(v);
-        Ok(self.buf)
+        Ok(4)
     }
     fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
         self.buf.put_i64_le(v);
-        Ok(self.buf)
+        Ok(8)
     }
     fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
         self.buf.put_u8(v);
-        Ok(self.buf)
+        Ok(1)
     }
     fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> {
         self.buf.put_u16_le(v);
-        Ok(self.buf)
+        Ok(2)
     }
     fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
         self.buf.put_u32_le(v);
-        Ok(self.buf)
+        Ok(4)
     }
     fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
         self.buf.put_u64_le(v);
-        Ok(self.buf)
+        Ok(8)
     }
     fn serialize_f32(self, _v: f32) -> Result<Self::Ok, Self::Error> {
-        Err(SerError::UnspecifiedType("f32").into())
+        Err(SerError::UnspecifiedType("f32"))
     }
     fn serialize_f64(self, _v: f64) -> Result<Self::Ok, Self::Error> {
-        Err(SerError::UnspecifiedType("f64").into())
+        Err(SerError::UnspecifiedType("f64"))
     }
-    fn serialize_char(self, _v: char) -> Result<Self::Ok, Self::Error> {
-        Err(SerError::UnspecifiedType("char").into())
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
+        require_extended(self.extended, "char")?;
+        let mut buf = [0u8; 4];
+        let encoded = v.encode_utf8(&mut buf);
+        self.buf.put_slice(encoded.as_bytes());
+        Ok(encoded.len() as u32)
     }
     fn serialize_str(self, s: &str) -> Result<Self::Ok, Self::Error> {
         if s.len() > u16::MAX as usize {
-            return Err(SerError::StringTooLong.into());
+            return Err(SerError::StringTooLong);
         }
         let len = s.len() as u16;
         self.buf.put_u16_le(len);
-        self.buf.put(s.as_bytes());
+        self.buf.put_slice(s.as_bytes());
 
-        Ok(self.buf)
+        Ok(len as u32 + 2)
     }
     fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
+        // Raw bytes are never left out, since they aren't a payload.
+        if mem::replace(&mut self.raw, false) {
+            let len = raw_len(v)?;
+            self.buf.put_slice(v);
+            return Ok(len);
+        }
         if v.len() > BYTES_LEN_MAX as usize {
-            return Err(SerError::BytesTooLong.into());
+            return Err(SerError::BytesTooLong);
         }
         self.buf.put_u32_le(v.len() as u32);
-        self.buf.put(v);
-        Ok(self.buf)
+        if let Some(elided) = self.elided.as_mut() {
+            *elided = elided.checked_add(v.len() as u32).ok_or(SerError::TooBig)?;
+            self.elided_end = self.buf.len();
+        } else {
+            self.buf.put_slice(v);
+        }
+        Ok(v.len() as u32 + 4)
     }
     fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
-        Err(SerError::UnspecifiedType("none").into())
+        require_extended(self.extended, "none")?;
+        0u8.serialize(self)
     }
-    fn serialize_some<T: ?Sized>(self, _value: &T) -> Result<Self::Ok, Self::Error>
+    fn serialize_some<T>(self, value: &T) -> Result<Self::Ok, Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        Err(SerError::UnspecifiedType("some").into())
+        require_extended(self.extended, "some")?;
+        let presence = 1u8.serialize(&mut *self)?;
+        let amount = value.serialize(self)?;
+        amount.checked_add(presence).ok_or(SerError::TooBig)
     }
     fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
-        Err(SerError::UnspecifiedType("unit").into())
+        Err(SerError::UnspecifiedType("unit"))
     }
     fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, Self::Error> {
-        Err(SerError::UnspecifiedType("unit struct").into())
+        Err(SerError::UnspecifiedType("unit struct"))
     }
     fn serialize_unit_variant(
         self,
         _name: &'static str,
-        _variant_index: u32,
+        variant_index: u32,
         _variant: &'static str,
     ) -> Result<Self::Ok, Self::Error> {
-        Err(SerError::UnspecifiedType("unit variant").into())
+        variant_tag(variant_index)?.serialize(self)
     }
-    fn serialize_newtype_struct<T: ?Sized>(
+    fn serialize_newtype_struct<T>(
         self,
-        _name: &'static str,
+        name: &'static str,
         value: &T,
     ) -> Result<Self::Ok, Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        value.serialize(self)
-    }
-    fn serialize_newtype_variant<T: ?Sized>(
+        if name == RAW_BYTES {
+            self.raw = true;
+            let res = value.serialize(&mut *self);
+            self.raw = false;
+            return res;
+        }
+        if name != SIZE_PREFIXED {
+            return value.serialize(self);
+        }
+
+        let size_pos = self.buf.len();
+        self.buf.put_u16_le(0);
+        let amount = value.serialize(&mut *self)?;
+        let size = prefixed_size(amount)?;
+        self.patch_u16(size_pos, size);
+        Ok(amount + 2)
+    }
+    fn serialize_newtype_variant<T>(
         self,
         _name: &'static str,
-        _variant_index: u32,
+        variant_index: u32,
         _variant: &'static str,
-        _value: &T,
+        value: &T,
     ) -> Result<Self::Ok, Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        Err(SerError::UnspecifiedType("newtype variant").into())
+        let tag = variant_tag(variant_index)?.serialize(&mut *self)?;
+        let amount = value.serialize(self)?;
+        amount.checked_add(tag).ok_or(SerError::TooBig)
     }
     fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
         if let Some(len) = len {
             if len > u16::MAX as usize {
-                return Err(SerError::SeqTooLong.into());
+                return Err(SerError::SeqTooLong);
             }
         }
 
-        self.seek_fwd(2)?;
-        Ok(CountingSequenceSerializer {
+        let count_pos = self.buf.len();
+        self.buf.put_u16_le(0);
+        Ok(BytesSequenceSerializer {
             serializer: self,
+            count_pos,
             current_count: 0,
             byte_count: 0,
         })
     }
     fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Self::Error> {
-        Ok(AccountingStructSerializer {
+        Ok(BytesStructSerializer {
             serializer: self,
             byte_count: 0,
-            size_behavior: StructSizeBehavior::None,
         })
     }
     fn serialize_tuple_struct(
@@ -153,58 +283,50 @@ impl Serializer for BytesSerializer {
         _name: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeTupleStruct, Self::Error> {
-        Ok(AccountingStructSerializer {
+        Ok(BytesStructSerializer {
             serializer: self,
             byte_count: 0,
-            size_behavior: StructSizeBehavior::None,
         })
     }
     fn serialize_tuple_variant(
         self,
         _name: &'static str,
-        _variant_index: u32,
+        variant_index: u32,
         _variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeTupleVariant, Self::Error> {
-        Err(SerError::UnspecifiedType("tuple variant").into())
+        let tag = variant_tag(variant_index)?.serialize(&mut *self)?;
+        Ok(BytesStructSerializer {
+            serializer: self,
+            byte_count: tag,
+        })
     }
-    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
-        Err(SerError::UnspecifiedType("map").into())
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        require_extended(self.extended, "map")?;
+        self.serialize_seq(len)
     }
     fn serialize_struct(
         self,
-        name: &'static str,
+        _name: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeStruct, Self::Error> {
-        let size_behavior = if name == "Twstat" || name == "Rstat" {
-            self.in_stat = true;
-            StructSizeBehavior::None
-        } else if name == "Stat" {
-            if self.in_stat {
-                self.seek_fwd(4)?;
-                StructSizeBehavior::DoubleTwo
-            } else {
-                self.seek_fwd(2)?;
-                StructSizeBehavior::Two
-            }
-        } else {
-            StructSizeBehavior::None
-        };
-
-        Ok(AccountingStructSerializer {
+        Ok(BytesStructSerializer {
             serializer: self,
             byte_count: 0,
-            size_behavior,
         })
     }
     fn serialize_struct_variant(
         self,
         _name: &'static str,
-        _variant_index: u32,
+        variant_index: u32,
         _variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeStructVariant, Self::Error> {
-        Err(SerError::UnspecifiedType("struct_variant").into())
+        let tag = variant_tag(variant_index)?.serialize(&mut *self)?;
+        Ok(BytesStructSerializer {
+            serializer: self,
+            byte_count: tag,
+        })
     }
 
     fn is_human_readable(&self) -> bool {
@@ -213,25 +335,23 @@ impl Serializer for BytesSerializer {
 }
 
 /// A sequence serializer that counts how many items it
-/// gets and then prefixes with the 2-byte count.
+/// gets and then patches in the 2-byte count.
+/// Maps are serialized the same way, counting key/value pairs.
 #[derive(Debug)]
-pub struct CountingSequenceSerializer {
+pub struct BytesSequenceSerializer<'ser, 'b> {
+    serializer: &'ser mut BytesSerializer<'b>,
+    count_pos: usize,
     current_count: u16,
-    count_bytes: BytesMut,
-    buf: BytesMut,
-}
-
-impl CountingSequenceSerializer {
-    fn new
+    byte_count: u32,
 }
 
-impl SerializeSeq for CountingSequenceSerializer {
+impl<'ser, 'b> SerializeSeq for BytesSequenceSerializer<'ser, 'b> {
     type Ok = u32;
     type Error = SerError;
 
-    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         self.current_count = self
             .current_count
@@ -243,47 +363,50 @@ impl SerializeSeq for CountingSequenceSerializer {
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        self.serializer.seek_back(self.byte_count)?;
-        self.serializer.seek_back(2)?;
-        self.current_count.serialize(&mut *self.serializer)?;
-        self.serializer.seek_fwd(self.byte_count)?;
-        self.byte_count
-            .checked_add(2)
-            .ok_or(SerError::TooBig.into())
+        self.serializer
+            .patch_u16(self.count_pos, self.current_count);
+        self.byte_count.checked_add(2).ok_or(SerError::TooBig)
     }
 }
 
-/// Tells a sub-serializer how to handle size-prefixing a struct.
-#[derive(Debug)]
-enum StructSizeBehavior {
-    /// A stat is being serialized to be sent for a directory read.
-    /// Prefix its two-bytes size.
-    Two,
-    /// A stat is being serialized for a stat-related message (twstat or rstat).
-    /// Its binary representation is prefixed with yet another two-byte size.
-    DoubleTwo,
-    /// This struct is not a Stat. Do not size-prefix.
-    None,
+impl<'ser, 'b> SerializeMap for BytesSequenceSerializer<'ser, 'b> {
+    type Ok = u32;
+    type Error = SerError;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        SerializeSeq::serialize_element(self, key)
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        let amt = value.serialize(&mut *self.serializer)?;
+        self.byte_count = self.byte_count.checked_add(amt).ok_or(SerError::TooBig)?;
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        SerializeSeq::end(self)
+    }
 }
 
 /// A struct serializer that counts the byte size of everything serialized so far.
 #[derive(Debug)]
-pub struct AccountingStructSerializer<'ser, W: 'ser + Write + Seek> {
-    serializer: &'ser mut WriteSerializer<W>,
+pub struct BytesStructSerializer<'ser, 'b> {
+    serializer: &'ser mut BytesSerializer<'b>,
     byte_count: u32,
-    size_behavior: StructSizeBehavior,
 }
 
-impl<'ser, W: 'ser + Write + Seek> SerializeStruct for AccountingStructSerializer<'ser, W> {
+impl<'ser, 'b> SerializeStruct for BytesStructSerializer<'ser, 'b> {
     type Ok = u32;
     type Error = SerError;
-    fn serialize_field<T: ?Sized>(
-        &mut self,
-        _key: &'static str,
-        value: &T,
-    ) -> Result<(), Self::Error>
+    fn serialize_field<T>(&mut self, _key: &'static str, value: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         SerializeTuple::serialize_element(self, value)
     }
@@ -292,65 +415,63 @@ impl<'ser, W: 'ser + Write + Seek> SerializeStruct for AccountingStructSerialize
     }
 }
 
-const STRUCT_SIZE_TWO_MAX: u32 = u16::MAX as u32 - 2;
-const STRUCT_SIZE_DOUBLE_TWO_MAX: u32 = u16::MAX as u32 - 4;
-
-impl<'ser, W: 'ser + Write + Seek> SerializeTuple for AccountingStructSerializer<'ser, W> {
+impl<'ser, 'b> SerializeTuple for BytesStructSerializer<'ser, 'b> {
     type Ok = u32;
     type Error = SerError;
-    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         let amount = value.serialize(&mut *self.serializer)?;
         self.byte_count = self
             .byte_count
             .checked_add(amount)
             .ok_or(SerError::TooBig)?;
+        Ok(())
+    }
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(self.byte_count)
+    }
+}
 
-        // TODO: is it quicker to check during end instead?
-        match self.size_behavior {
-            StructSizeBehavior::Two if self.byte_count > STRUCT_SIZE_TWO_MAX => {
-                Err(SerError::TooBig.into())
-            }
-            StructSizeBehavior::DoubleTwo if self.byte_count > STRUCT_SIZE_DOUBLE_TWO_MAX => {
-                Err(SerError::TooBig.into())
-            }
-            _ => Ok(()),
-        }
+impl<'ser, 'b> SerializeTupleStruct for BytesStructSerializer<'ser, 'b> {
+    type Ok = u32;
+    type Error = SerError;
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        SerializeTuple::serialize_element(self, value)
     }
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        Ok(match self.size_behavior {
-            StructSizeBehavior::None => self.byte_count,
-            StructSizeBehavior::Two => {
-                self.serializer.seek_back(self.byte_count)?;
-                self.serializer.seek_back(2)?;
-                (self.byte_count as u16).serialize(&mut *self.serializer)?;
-                self.serializer.seek_fwd(self.byte_count)?;
-                self.byte_count + 2
-            }
-            StructSizeBehavior::DoubleTwo => {
-                self.serializer.seek_back(self.byte_count)?;
-                self.serializer.seek_back(4)?;
-                (self.byte_count as u16 + 2).serialize(&mut *self.serializer)?;
-                (self.byte_count as u16 + 0).serialize(&mut *self.serializer)?;
-                self.serializer.seek_fwd(self.byte_count)?;
-                self.byte_count + 4
-            }
-        })
+        SerializeTuple::end(self)
+    }
+}
+
+impl<'ser, 'b> SerializeTupleVariant for BytesStructSerializer<'ser, 'b> {
+    type Ok = u32;
+    type Error = SerError;
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        SerializeTuple::serialize_element(self, value)
+    }
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        SerializeTuple::end(self)
     }
 }
 
-impl<'ser, W: 'ser + Write + Seek> SerializeTupleStruct for AccountingStructSerializer<'ser, W> {
+impl<'ser, 'b> SerializeStructVariant for BytesStructSerializer<'ser, 'b> {
     type Ok = u32;
     type Error = SerError;
-    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
+    fn serialize_field<T>(&mut self, _key: &'static str, value: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         SerializeTuple::serialize_element(self, value)
     }
     fn end(self) -> Result<Self::Ok, Self::Error> {
         SerializeTuple::end(self)
     }
-}
\ No newline at end of file
+}
@@ -4,6 +4,8 @@ use std::fmt::Display;
 use std::io;
 use thiserror::Error;
 
+pub(crate) use crate::message::{RAW_BYTES, SIZE_PREFIXED};
+
 /// A serializer function that serializes any byte slice like object.
 pub fn serialize_bytes<T, S>(t: &T, serializer: S) -> Result<S::Ok, S::Error>
 where
@@ -30,8 +32,12 @@ pub enum SerError {
     TooBig,
     #[error("Type {0} is unspecified in 9p")]
     UnspecifiedType(&'static str),
+    #[error("Frame size {size} exceeds the msize of {msize}")]
+    ExceedsMsize { size: u32, msize: u32 },
     #[error("{0}")]
     Unsupported(&'static str),
+    #[error("Enum variant index {0} doesn't fit in a u8")]
+    VariantIndexTooBig(u32),
 }
 
 impl ser::Error for SerError {
@@ -67,17 +73,43 @@ impl ser::Error for SerErrorWithIo {
 }
 //endregion
 
-/// Tells a sub-serializer how to handle size-prefixing a struct.
-#[derive(Debug)]
-pub(crate) enum StructSizeBehavior {
-    /// A stat is being serialized to be sent for a directory read.
-    /// Prefix its two-bytes size.
-    Two,
-    /// A stat is being serialized for a stat-related message (twstat or rstat).
-    /// Its binary representation is prefixed with yet another two-byte size.
-    DoubleTwo,
-    /// This struct is not a Stat. Do not size-prefix.
-    None,
+/// Fail unless the extended encodings are enabled, naming the type that needs them.
+pub(crate) fn require_extended(extended: bool, type_name: &'static str) -> Result<(), SerError> {
+    if extended {
+        Ok(())
+    } else {
+        Err(SerError::UnspecifiedType(type_name))
+    }
+}
+
+/// The length of a byte array written raw, with no length prefix,
+/// failing if it's too long to be counted.
+pub(crate) fn raw_len(v: &[u8]) -> Result<u32, SerError> {
+    if v.len() > u32::MAX as usize {
+        Err(SerError::TooBig)
+    } else {
+        Ok(v.len() as u32)
+    }
+}
+
+/// The one-byte tag that starts a serialized enum variant with the given index,
+/// failing if it doesn't fit.
+pub(crate) fn variant_tag(variant_index: u32) -> Result<u8, SerError> {
+    if variant_index > u8::MAX as u32 {
+        Err(SerError::VariantIndexTooBig(variant_index))
+    } else {
+        Ok(variant_index as u8)
+    }
+}
+
+/// The two-byte size prefix for a size-prefixed value of the given length,
+/// failing if it's too long to be described by one.
+pub(crate) fn prefixed_size(amount: u32) -> Result<u16, SerError> {
+    if amount > u16::MAX as u32 - 2 {
+        Err(SerError::TooBig)
+    } else {
+        Ok(amount as u16)
+    }
 }
 
 /// The maximum possible length of a byte array in 9p.
@@ -1,9 +1,36 @@
 pub use super::common::*;
+use super::frame::{header_len, EncoderConfig};
+use crate::message::MessageTypeId;
 use serde::ser::*;
+use std::mem;
 
 #[derive(Debug)]
 struct SizeCounterSerializer {
-    in_stat: bool, // if in a rstat/twstat message, double-prefix the size
+    /// If recording, the sequence counts and value sizes that prefix
+    /// their contents, in the order they'd be written.
+    prefixes: Option<Vec<u32>>,
+    /// Whether the extended encodings are allowed.
+    extended: bool,
+    /// Whether the next byte array is written raw, with no length prefix,
+    /// as it is directly inside a `RAW_BYTES` newtype struct.
+    raw: bool,
+}
+
+impl SizeCounterSerializer {
+    /// Reserve a spot for a prefix that is only known once its contents are counted.
+    fn reserve_prefix(&mut self) -> Option<usize> {
+        self.prefixes.as_mut().map(|prefixes| {
+            prefixes.push(0);
+            prefixes.len() - 1
+        })
+    }
+
+    /// Fill in a reserved prefix.
+    fn fill_prefix(&mut self, index: Option<usize>, value: u32) {
+        if let (Some(prefixes), Some(index)) = (self.prefixes.as_mut(), index) {
+            prefixes[index] = value;
+        }
+    }
 }
 
 impl<'ser> Serializer for &'ser mut SizeCounterSerializer {
@@ -13,10 +40,10 @@ impl<'ser> Serializer for &'ser mut SizeCounterSerializer {
     type SerializeSeq = CountingSequenceSerializer<'ser>;
     type SerializeTuple = AccountingStructSerializer<'ser>;
     type SerializeTupleStruct = AccountingStructSerializer<'ser>;
-    type SerializeTupleVariant = Unimplemented;
-    type SerializeMap = Unimplemented;
+    type SerializeTupleVariant = AccountingStructSerializer<'ser>;
+    type SerializeMap = CountingSequenceSerializer<'ser>;
     type SerializeStruct = AccountingStructSerializer<'ser>;
-    type SerializeStructVariant = Unimplemented;
+    type SerializeStructVariant = AccountingStructSerializer<'ser>;
 
     fn serialize_bool(self, _v: bool) -> Result<Self::Ok, Self::Error> {
         Ok(1)
@@ -51,8 +78,9 @@ impl<'ser> Serializer for &'ser mut SizeCounterSerializer {
     fn serialize_f64(self, _v: f64) -> Result<Self::Ok, Self::Error> {
         Err(SerError::UnspecifiedType("f64"))
     }
-    fn serialize_char(self, _v: char) -> Result<Self::Ok, Self::Error> {
-        Err(SerError::UnspecifiedType("char"))
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
+        require_extended(self.extended, "char")?;
+        Ok(v.len_utf8() as u32)
     }
     fn serialize_str(self, s: &str) -> Result<Self::Ok, Self::Error> {
         if s.len() > u16::MAX as usize {
@@ -63,19 +91,25 @@ impl<'ser> Serializer for &'ser mut SizeCounterSerializer {
         Ok(len as u32 + 2)
     }
     fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
+        if mem::replace(&mut self.raw, false) {
+            return raw_len(v);
+        }
         if v.len() > BYTES_LEN_MAX as usize {
             return Err(SerError::BytesTooLong);
         }
         Ok(v.len() as u32 + 4)
     }
     fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
-        Err(SerError::UnspecifiedType("none"))
+        require_extended(self.extended, "none")?;
+        Ok(1)
     }
-    fn serialize_some<T: ?Sized>(self, _value: &T) -> Result<Self::Ok, Self::Error>
+    fn serialize_some<T: ?Sized>(self, value: &T) -> Result<Self::Ok, Self::Error>
     where
         T: Serialize,
     {
-        Err(SerError::UnspecifiedType("some"))
+        require_extended(self.extended, "some")?;
+        let amount = value.serialize(self)?;
+        amount.checked_add(1).ok_or(SerError::TooBig)
     }
     fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
         Err(SerError::UnspecifiedType("unit"))
@@ -86,32 +120,47 @@ impl<'ser> Serializer for &'ser mut SizeCounterSerializer {
     fn serialize_unit_variant(
         self,
         _name: &'static str,
-        _variant_index: u32,
+        variant_index: u32,
         _variant: &'static str,
     ) -> Result<Self::Ok, Self::Error> {
-        Err(SerError::UnspecifiedType("unit variant"))
+        variant_tag(variant_index)?.serialize(self)
     }
     fn serialize_newtype_struct<T: ?Sized>(
         self,
-        _name: &'static str,
+        name: &'static str,
         value: &T,
     ) -> Result<Self::Ok, Self::Error>
     where
         T: Serialize,
     {
-        value.serialize(self)
+        if name == RAW_BYTES {
+            self.raw = true;
+            let res = value.serialize(&mut *self);
+            self.raw = false;
+            return res;
+        }
+        if name != SIZE_PREFIXED {
+            return value.serialize(self);
+        }
+
+        let prefix = self.reserve_prefix();
+        let amount = value.serialize(&mut *self)?;
+        self.fill_prefix(prefix, prefixed_size(amount)? as u32);
+        Ok(amount + 2)
     }
     fn serialize_newtype_variant<T: ?Sized>(
         self,
         _name: &'static str,
-        _variant_index: u32,
+        variant_index: u32,
         _variant: &'static str,
-        _value: &T,
+        value: &T,
     ) -> Result<Self::Ok, Self::Error>
     where
         T: Serialize,
     {
-        Err(SerError::UnspecifiedType("newtype variant"))
+        let tag = variant_tag(variant_index)?.serialize(&mut *self)?;
+        let amount = value.serialize(self)?;
+        amount.checked_add(tag).ok_or(SerError::TooBig)
     }
     fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
         if let Some(len) = len {
@@ -120,6 +169,7 @@ impl<'ser> Serializer for &'ser mut SizeCounterSerializer {
             }
         }
         Ok(CountingSequenceSerializer {
+            prefix: self.reserve_prefix(),
             serializer: self,
             current_count: 0,
             byte_count: 0,
@@ -129,7 +179,6 @@ impl<'ser> Serializer for &'ser mut SizeCounterSerializer {
         Ok(AccountingStructSerializer {
             serializer: self,
             byte_count: 0,
-            size_behavior: StructSizeBehavior::None,
         })
     }
     fn serialize_tuple_struct(
@@ -140,53 +189,47 @@ impl<'ser> Serializer for &'ser mut SizeCounterSerializer {
         Ok(AccountingStructSerializer {
             serializer: self,
             byte_count: 0,
-            size_behavior: StructSizeBehavior::None,
         })
     }
     fn serialize_tuple_variant(
         self,
         _name: &'static str,
-        _variant_index: u32,
+        variant_index: u32,
         _variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeTupleVariant, Self::Error> {
-        Err(SerError::UnspecifiedType("tuple variant").into())
+        let tag = variant_tag(variant_index)?.serialize(&mut *self)?;
+        Ok(AccountingStructSerializer {
+            serializer: self,
+            byte_count: tag,
+        })
     }
-    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
-        Err(SerError::UnspecifiedType("map").into())
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        require_extended(self.extended, "map")?;
+        self.serialize_seq(len)
     }
     fn serialize_struct(
         self,
-        name: &'static str,
+        _name: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeStruct, Self::Error> {
-        let size_behavior = if name == "Twstat" || name == "Rstat" {
-            self.in_stat = true;
-            StructSizeBehavior::None
-        } else if name == "Stat" {
-            if self.in_stat {
-                StructSizeBehavior::DoubleTwo
-            } else {
-                StructSizeBehavior::Two
-            }
-        } else {
-            StructSizeBehavior::None
-        };
-
         Ok(AccountingStructSerializer {
             serializer: self,
             byte_count: 0,
-            size_behavior,
         })
     }
     fn serialize_struct_variant(
         self,
         _name: &'static str,
-        _variant_index: u32,
+        variant_index: u32,
         _variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeStructVariant, Self::Error> {
-        Err(SerError::UnspecifiedType("struct_variant"))
+        let tag = variant_tag(variant_index)?.serialize(&mut *self)?;
+        Ok(AccountingStructSerializer {
+            serializer: self,
+            byte_count: tag,
+        })
     }
 
     fn is_human_readable(&self) -> bool {
@@ -196,9 +239,11 @@ impl<'ser> Serializer for &'ser mut SizeCounterSerializer {
 
 /// A sequence serializer that counts how many items it
 /// gets and then prefixes with the 2-byte count.
+/// Maps are serialized the same way, counting key/value pairs.
 #[derive(Debug)]
 pub struct CountingSequenceSerializer<'ser> {
     serializer: &'ser mut SizeCounterSerializer,
+    prefix: Option<usize>,
     current_count: u16,
     byte_count: u32,
 }
@@ -221,18 +266,42 @@ impl<'ser> SerializeSeq for CountingSequenceSerializer<'ser> {
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.serializer
+            .fill_prefix(self.prefix, self.current_count as u32);
         self.byte_count.checked_add(2).ok_or(SerError::TooBig)
     }
 }
 
+impl<'ser> SerializeMap for CountingSequenceSerializer<'ser> {
+    type Ok = u32;
+    type Error = SerError;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        SerializeSeq::serialize_element(self, key)
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        let amt = value.serialize(&mut *self.serializer)?;
+        self.byte_count = self.byte_count.checked_add(amt).ok_or(SerError::TooBig)?;
+        Ok(())
+    }
 
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        SerializeSeq::end(self)
+    }
+}
 
 /// A struct serializer that counts the byte size of everything serialized so far.
 #[derive(Debug)]
 pub struct AccountingStructSerializer<'ser> {
     serializer: &'ser mut SizeCounterSerializer,
     byte_count: u32,
-    size_behavior: StructSizeBehavior,
 }
 
 impl<'ser> SerializeStruct for AccountingStructSerializer<'ser> {
@@ -253,9 +322,6 @@ impl<'ser> SerializeStruct for AccountingStructSerializer<'ser> {
     }
 }
 
-const STRUCT_SIZE_TWO_MAX: u32 = u16::MAX as u32 - 2;
-const STRUCT_SIZE_DOUBLE_TWO_MAX: u32 = u16::MAX as u32 - 4;
-
 impl<'ser> SerializeTuple for AccountingStructSerializer<'ser> {
     type Ok = u32;
     type Error = SerError;
@@ -268,24 +334,10 @@ impl<'ser> SerializeTuple for AccountingStructSerializer<'ser> {
             .byte_count
             .checked_add(amount)
             .ok_or(SerError::TooBig)?;
-
-        // TODO: is it quicker to check during end instead?
-        match self.size_behavior {
-            StructSizeBehavior::Two if self.byte_count > STRUCT_SIZE_TWO_MAX => {
-                Err(SerError::TooBig.into())
-            }
-            StructSizeBehavior::DoubleTwo if self.byte_count > STRUCT_SIZE_DOUBLE_TWO_MAX => {
-                Err(SerError::TooBig.into())
-            }
-            _ => Ok(()),
-        }
+        Ok(())
     }
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        Ok(match self.size_behavior {
-            StructSizeBehavior::None => self.byte_count,
-            StructSizeBehavior::Two => self.byte_count + 2,
-            StructSizeBehavior::DoubleTwo => self.byte_count + 4,
-        })
+        Ok(self.byte_count)
     }
 }
 
@@ -303,65 +355,112 @@ impl<'ser> SerializeTupleStruct for AccountingStructSerializer<'ser> {
     }
 }
 
-//region Unimplemented
-/// Stand-in code for types of serialization that will never happen
-/// because the types are unspecified.
-enum Unimplemented {}
-
-impl SerializeMap for Unimplemented {
+impl<'ser> SerializeTupleVariant for AccountingStructSerializer<'ser> {
     type Ok = u32;
     type Error = SerError;
-    fn serialize_key<T: ?Sized>(&mut self, _key: &T) -> Result<(), Self::Error>
-    where
-        T: Serialize,
-    {
-        unreachable!()
-    }
-    fn serialize_value<T: ?Sized>(&mut self, _value: &T) -> Result<(), Self::Error>
-    where
-        T: Serialize,
-    {
-        unreachable!()
-    }
-    fn end(self) -> Result<Self::Ok, Self::Error> {
-        unreachable!()
-    }
-}
-
-impl SerializeTupleVariant for Unimplemented {
-    type Ok = u32;
-    type Error = SerError;
-    fn serialize_field<T: ?Sized>(&mut self, _value: &T) -> Result<(), Self::Error>
+    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
     where
         T: Serialize,
     {
-        unreachable!()
+        SerializeTuple::serialize_element(self, value)
     }
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        unreachable!()
+        SerializeTuple::end(self)
     }
 }
 
-impl SerializeStructVariant for Unimplemented {
+impl<'ser> SerializeStructVariant for AccountingStructSerializer<'ser> {
     type Ok = u32;
     type Error = SerError;
     fn serialize_field<T: ?Sized>(
         &mut self,
         _key: &'static str,
-        _value: &T,
+        value: &T,
     ) -> Result<(), Self::Error>
     where
         T: Serialize,
     {
-        unreachable!()
+        SerializeTuple::serialize_element(self, value)
     }
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        unreachable!()
+        SerializeTuple::end(self)
     }
 }
-//endregion
 
-pub fn size_for<T: Serialize>(t: &T) -> Result<u32, SerError> {
-    let mut counter = SizeCounterSerializer { in_stat: false };
+/// Count how many bytes the given item takes up when serialized,
+/// without serializing it anywhere.
+///
+/// This follows the same rules as the other serializers, including
+/// size prefixes such as the doubled one of the stat in `Rstat` and `Twstat`,
+/// so it can be used to preallocate an exact-size buffer or to see
+/// how much of a message fits within an msize.
+/// ```
+/// # use nine::ser::*;
+/// use nine::p2000::Rclunk;
+/// let rclunk = Rclunk { tag: 1 };
+/// assert_eq!(serialized_size(&rclunk).unwrap(), 2);
+/// assert_eq!(serialized_size(&rclunk).unwrap() as usize, into_bytes(&rclunk).unwrap().len());
+/// ```
+pub fn serialized_size<T: Serialize>(t: &T) -> Result<u32, SerError> {
+    serialized_size_with(t, &EncoderConfig::default())
+}
+
+/// Count how many bytes the given item takes up when serialized,
+/// using the given configuration.
+///
+/// Only whether the extended encodings are allowed matters here.
+pub fn serialized_size_with<T: Serialize>(t: &T, config: &EncoderConfig) -> Result<u32, SerError> {
+    let mut counter = SizeCounterSerializer {
+        prefixes: None,
+        extended: config.extended,
+        raw: false,
+    };
     t.serialize(&mut counter)
-}
\ No newline at end of file
+}
+
+/// Count how many bytes the given message takes up as a whole frame,
+/// `size[4] type[1]` header included. This is the value of its size field.
+/// ```
+/// # use nine::ser::*;
+/// use nine::p2000::Rclunk;
+/// let rclunk = Rclunk { tag: 1 };
+/// assert_eq!(serialized_frame_size(&rclunk).unwrap(), 7);
+/// ```
+pub fn serialized_frame_size<T: Serialize + MessageTypeId>(t: &T) -> Result<u32, SerError> {
+    serialized_frame_size_with(t, &EncoderConfig::default())
+}
+
+/// Count how many bytes the given message takes up as a whole frame,
+/// using the given configuration.
+///
+/// Only whether the extended encodings are allowed matters here;
+/// the frame isn't checked against the msize.
+pub fn serialized_frame_size_with<T: Serialize + MessageTypeId>(
+    t: &T,
+    config: &EncoderConfig,
+) -> Result<u32, SerError> {
+    serialized_size_with(t, config)?
+        .checked_add(header_len(t))
+        .ok_or(SerError::TooBig)
+}
+
+/// Count how many bytes the given item takes up when serialized.
+#[deprecated(note = "renamed to `serialized_size`")]
+pub fn size_for<T: Serialize>(t: &T) -> Result<u32, SerError> {
+    serialized_size(t)
+}
+
+/// Count the size of the given item, along with the sequence counts and
+/// value sizes that prefix its contents, in the order they'd be written.
+pub(crate) fn prefixes_for<T: Serialize>(
+    t: &T,
+    extended: bool,
+) -> Result<(u32, Vec<u32>), SerError> {
+    let mut counter = SizeCounterSerializer {
+        prefixes: Some(Vec::new()),
+        extended,
+        raw: false,
+    };
+    let size = t.serialize(&mut counter)?;
+    Ok((size, counter.prefixes.unwrap_or_default()))
+}
@@ -0,0 +1,198 @@
+use super::common::*;
+use super::count::prefixes_for;
+use super::stream::stream_with_prefixes;
+use super::write_seek::*;
+use crate::message::{MessageTypeId, FRAME_HEADER_LEN};
+use byteorder::{LittleEndian, WriteBytesExt};
+use std::io::{Cursor, Seek, SeekFrom, Write};
+
+/// Configuration for encoding frames.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncoderConfig {
+    /// The largest frame that may be sent, header included.
+    /// This is typically the msize agreed on by `Tversion` and `Rversion`.
+    pub msize: u32,
+    /// Whether to write the [extended encodings](crate::ser#extended-encodings).
+    pub extended: bool,
+}
+
+impl Default for EncoderConfig {
+    /// A configuration that allows frames of any size,
+    /// as is needed before an msize has been negotiated.
+    fn default() -> Self {
+        EncoderConfig {
+            msize: u32::MAX,
+            extended: false,
+        }
+    }
+}
+
+impl EncoderConfig {
+    /// A configuration that rejects frames bigger than the given msize.
+    pub fn with_msize(msize: u32) -> Self {
+        EncoderConfig {
+            msize,
+            ..Self::default()
+        }
+    }
+}
+
+/// The length of the header a frame adds in front of the given serialized message:
+/// just its size if the message writes its own type ID, as a message set does,
+/// and its size and type ID otherwise.
+pub(crate) fn header_len<T: MessageTypeId>(t: &T) -> u32 {
+    if t.serializes_type_id() {
+        FRAME_HEADER_LEN - 1
+    } else {
+        FRAME_HEADER_LEN
+    }
+}
+
+/// Serialize the given message into a new vec buffer as a whole frame,
+/// `size[4] type[1]` header included.
+/// ```
+/// # use nine::ser::*;
+/// use nine::p2000::Rclunk;
+/// let frame = into_frame(&Rclunk { tag: 1 }).unwrap();
+/// assert_eq!(frame, [7, 0, 0, 0, 121, 1, 0]);
+/// ```
+pub fn into_frame<T: Serialize + MessageTypeId>(t: &T) -> Result<Vec<u8>, SerError> {
+    into_frame_with(t, &EncoderConfig::default())
+}
+
+/// Serialize the given message into a new vec buffer as a whole frame,
+/// using the given configuration.
+pub fn into_frame_with<T: Serialize + MessageTypeId>(
+    t: &T,
+    config: &EncoderConfig,
+) -> Result<Vec<u8>, SerError> {
+    let mut vec = Vec::new();
+    append_frame_with(t, &mut vec, config)?;
+    Ok(vec)
+}
+
+/// Serializes the given message as a whole frame at the _end_ of the given Vec.
+///
+/// Returns the size of the frame, which is the number of bytes written.
+/// ```
+/// # use nine::ser::*;
+/// use nine::p2000::Rerror;
+/// let mut buf = Vec::new();
+/// let err = Rerror { tag: 0, ename: "foo".into() };
+/// let size = append_frame(&err, &mut buf).unwrap();
+/// assert_eq!(size as usize, buf.len());
+/// ```
+pub fn append_frame<T: Serialize + MessageTypeId, V: AsMut<Vec<u8>>>(
+    t: &T,
+    vec: V,
+) -> Result<u32, SerError> {
+    append_frame_with(t, vec, &EncoderConfig::default())
+}
+
+/// Serializes the given message as a whole frame at the _end_ of the given Vec,
+/// using the given configuration.
+///
+/// Returns the size of the frame, which is the number of bytes written.
+/// If the frame can't be serialized or is bigger than the msize,
+/// the Vec is left the way it was.
+/// ```
+/// # use nine::ser::*;
+/// use nine::p2000::Rerror;
+/// let mut buf = Vec::new();
+/// let err = Rerror { tag: 0, ename: "a very long error".into() };
+/// let config = EncoderConfig::with_msize(16);
+/// assert!(append_frame_with(&err, &mut buf, &config).is_err());
+/// assert!(buf.is_empty());
+/// ```
+pub fn append_frame_with<T: Serialize + MessageTypeId, V: AsMut<Vec<u8>>>(
+    t: &T,
+    mut vec: V,
+    config: &EncoderConfig,
+) -> Result<u32, SerError> {
+    let vec = vec.as_mut();
+    let start = vec.len();
+    let res = write_frame_at(t, &mut *vec, start as u64, config);
+    if res.is_err() {
+        vec.truncate(start);
+    }
+    res
+}
+
+/// Serializes a whole frame into the vec starting at the given position.
+fn write_frame_at<T: Serialize + MessageTypeId>(
+    t: &T,
+    vec: &mut Vec<u8>,
+    start: u64,
+    config: &EncoderConfig,
+) -> Result<u32, SerError> {
+    let header_len = header_len(t);
+    let mut writer = Cursor::new(vec);
+    writer
+        .seek(SeekFrom::Start(start + header_len as u64))
+        .unwrap();
+
+    let mut ser = WriteSerializer::new(writer);
+    if config.extended {
+        ser = ser.extended();
+    }
+    let body_size = t.serialize(&mut ser).map_err(|e| e.unwrap_ser_error())?;
+    let size = body_size.checked_add(header_len).ok_or(SerError::TooBig)?;
+    if size > config.msize {
+        return Err(SerError::ExceedsMsize {
+            size,
+            msize: config.msize,
+        });
+    }
+
+    let mut writer = ser.into_writer();
+    writer.set_position(start);
+    writer.write_u32::<LittleEndian>(size).unwrap();
+    if !t.serializes_type_id() {
+        writer.write_u8(t.msg_type_id()).unwrap();
+    }
+
+    Ok(size)
+}
+
+/// Serializes the given message as a whole frame and writes it to the given writer.
+///
+/// The message is counted first, so that a failure to serialize
+/// never leaves a partial frame in the writer, and is then streamed out
+/// without seeking or building the frame in memory.
+/// Many small writes are made, so an unbuffered writer should be wrapped
+/// in a `BufWriter`.
+///
+/// Returns the size of the frame, which is the number of bytes written.
+pub fn write_message<T: Serialize + MessageTypeId, W: Write>(
+    t: &T,
+    writer: W,
+) -> Result<u32, SerErrorWithIo> {
+    write_message_with(t, writer, &EncoderConfig::default())
+}
+
+/// Serializes the given message as a whole frame and writes it to the given writer,
+/// using the given configuration.
+pub fn write_message_with<T: Serialize + MessageTypeId, W: Write>(
+    t: &T,
+    mut writer: W,
+    config: &EncoderConfig,
+) -> Result<u32, SerErrorWithIo> {
+    let (body_size, prefixes) = prefixes_for(t, config.extended)?;
+    let size = body_size
+        .checked_add(header_len(t))
+        .ok_or(SerError::TooBig)?;
+    if size > config.msize {
+        return Err(SerError::ExceedsMsize {
+            size,
+            msize: config.msize,
+        }
+        .into());
+    }
+
+    writer.write_u32::<LittleEndian>(size)?;
+    if !t.serializes_type_id() {
+        writer.write_u8(t.msg_type_id())?;
+    }
+    stream_with_prefixes(t, writer, prefixes, config.extended)?;
+    Ok(size)
+}
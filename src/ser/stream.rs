@@ -0,0 +1,434 @@
+use super::common::*;
+use super::count::prefixes_for;
+use super::frame::EncoderConfig;
+use byteorder::{LittleEndian, WriteBytesExt};
+use serde::ser::*;
+use std::io::Write;
+use std::mem;
+use std::vec;
+
+/// Serializes the given item into any type that implements `Write`,
+/// such as a socket or a pipe, in a single pass with no seeking.
+///
+/// The item is first counted, to learn the sizes that prefix its contents,
+/// so nothing is written at all if it can't be serialized.
+/// Many small writes are made, so an unbuffered writer should be wrapped
+/// in a `BufWriter`.
+///
+/// Returns the number of bytes written.
+/// ```
+/// # use nine::ser::*;
+/// use nine::p2000::Twalk;
+/// let walk = Twalk { tag: 0, fid: 0, newfid: 1, wname: vec!["foo".into()] };
+/// let mut out = Vec::new();
+/// let amount = into_writer(&walk, &mut out).unwrap();
+/// assert_eq!(amount as usize, out.len());
+/// assert_eq!(out, into_bytes(&walk).unwrap());
+/// ```
+pub fn into_writer<T: Serialize, W: Write>(t: &T, writer: W) -> Result<u32, SerErrorWithIo> {
+    into_writer_with(t, writer, &EncoderConfig::default())
+}
+
+/// Serializes the given item into any type that implements `Write`
+/// like `into_writer`, using the given configuration.
+///
+/// Only whether the extended encodings are allowed matters here.
+pub fn into_writer_with<T: Serialize, W: Write>(
+    t: &T,
+    writer: W,
+    config: &EncoderConfig,
+) -> Result<u32, SerErrorWithIo> {
+    let (_, prefixes) = prefixes_for(t, config.extended)?;
+    stream_with_prefixes(t, writer, prefixes, config.extended)
+}
+
+/// Write the given item using prefixes already counted by `prefixes_for`,
+/// with the extended encodings allowed the same way they were when counting.
+pub(crate) fn stream_with_prefixes<T: ?Sized + Serialize, W: Write>(
+    t: &T,
+    writer: W,
+    prefixes: Vec<u32>,
+    extended: bool,
+) -> Result<u32, SerErrorWithIo> {
+    let mut ser = StreamSerializer {
+        writer,
+        prefixes: prefixes.into_iter(),
+        extended,
+        raw: false,
+    };
+
+    t.serialize(&mut ser)
+}
+
+/// A serializer that writes straight through to any type that implements `Write`,
+/// using sizes counted ahead of time for anything that needs a size prefix.
+#[derive(Debug)]
+struct StreamSerializer<W: Write> {
+    writer: W,
+    /// The sequence counts and value sizes, in the order they're written.
+    prefixes: vec::IntoIter<u32>,
+    /// Whether the extended encodings are allowed.
+    extended: bool,
+    /// Whether the next byte array is written raw, with no length prefix,
+    /// as it is directly inside a `RAW_BYTES` newtype struct.
+    raw: bool,
+}
+
+impl<W: Write> StreamSerializer<W> {
+    /// Take the next precounted prefix.
+    fn next_prefix(&mut self) -> Result<u32, SerError> {
+        self.prefixes.next().ok_or(SerError::Unsupported(
+            "item serialized differently than when it was counted",
+        ))
+    }
+}
+
+impl<'ser, W: 'ser + Write> Serializer for &'ser mut StreamSerializer<W> {
+    type Ok = u32;
+    type Error = SerErrorWithIo;
+
+    type SerializeSeq = StreamSequenceSerializer<'ser, W>;
+    type SerializeTuple = StreamStructSerializer<'ser, W>;
+    type SerializeTupleStruct = StreamStructSerializer<'ser, W>;
+    type SerializeTupleVariant = StreamStructSerializer<'ser, W>;
+    type SerializeMap = StreamSequenceSerializer<'ser, W>;
+    type SerializeStruct = StreamStructSerializer<'ser, W>;
+    type SerializeStructVariant = StreamStructSerializer<'ser, W>;
+
+    fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
+        self.writer.write_u8(v as u8)?;
+        Ok(1)
+    }
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok, Self::Error> {
+        self.writer.write_i8(v)?;
+        Ok(1)
+    }
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok, Self::Error> {
+        self.writer.write_i16::<LittleEndian>(v)?;
+        Ok(2)
+    }
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok, Self::Error> {
+        self.writer.write_i32::<LittleEndian>(v)?;
+        Ok(4)
+    }
+    fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
+        self.writer.write_i64::<LittleEndian>(v)?;
+        Ok(8)
+    }
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
+        self.writer.write_u8(v)?;
+        Ok(1)
+    }
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> {
+        self.writer.write_u16::<LittleEndian>(v)?;
+        Ok(2)
+    }
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
+        self.writer.write_u32::<LittleEndian>(v)?;
+        Ok(4)
+    }
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
+        self.writer.write_u64::<LittleEndian>(v)?;
+        Ok(8)
+    }
+    fn serialize_f32(self, _v: f32) -> Result<Self::Ok, Self::Error> {
+        Err(SerError::UnspecifiedType("f32").into())
+    }
+    fn serialize_f64(self, _v: f64) -> Result<Self::Ok, Self::Error> {
+        Err(SerError::UnspecifiedType("f64").into())
+    }
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
+        require_extended(self.extended, "char")?;
+        let mut buf = [0u8; 4];
+        let encoded = v.encode_utf8(&mut buf);
+        self.writer.write_all(encoded.as_bytes())?;
+        Ok(encoded.len() as u32)
+    }
+    fn serialize_str(self, s: &str) -> Result<Self::Ok, Self::Error> {
+        if s.len() > u16::MAX as usize {
+            return Err(SerError::StringTooLong.into());
+        }
+        let len = s.len() as u16;
+        self.writer.write_u16::<LittleEndian>(len)?;
+        self.writer.write_all(s.as_bytes())?;
+
+        Ok(len as u32 + 2)
+    }
+    fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
+        if mem::replace(&mut self.raw, false) {
+            let len = raw_len(v)?;
+            self.writer.write_all(v)?;
+            return Ok(len);
+        }
+        if v.len() > BYTES_LEN_MAX as usize {
+            return Err(SerError::BytesTooLong.into());
+        }
+        self.writer.write_u32::<LittleEndian>(v.len() as u32)?;
+        self.writer.write_all(v)?;
+        Ok(v.len() as u32 + 4)
+    }
+    fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+        require_extended(self.extended, "none")?;
+        0u8.serialize(self)
+    }
+    fn serialize_some<T>(self, value: &T) -> Result<Self::Ok, Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        require_extended(self.extended, "some")?;
+        let presence = 1u8.serialize(&mut *self)?;
+        let amount = value.serialize(self)?;
+        Ok(amount.checked_add(presence).ok_or(SerError::TooBig)?)
+    }
+    fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
+        Err(SerError::UnspecifiedType("unit").into())
+    }
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, Self::Error> {
+        Err(SerError::UnspecifiedType("unit struct").into())
+    }
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<Self::Ok, Self::Error> {
+        variant_tag(variant_index)?.serialize(self)
+    }
+    fn serialize_newtype_struct<T>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        if name == RAW_BYTES {
+            self.raw = true;
+            let res = value.serialize(&mut *self);
+            self.raw = false;
+            return res;
+        }
+        if name != SIZE_PREFIXED {
+            return value.serialize(self);
+        }
+
+        let size = self.next_prefix()?;
+        self.writer.write_u16::<LittleEndian>(size as u16)?;
+        let amount = value.serialize(&mut *self)?;
+        Ok(amount + 2)
+    }
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        let tag = variant_tag(variant_index)?.serialize(&mut *self)?;
+        let amount = value.serialize(self)?;
+        Ok(amount.checked_add(tag).ok_or(SerError::TooBig)?)
+    }
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        let count = self.next_prefix()?;
+        self.writer.write_u16::<LittleEndian>(count as u16)?;
+
+        Ok(StreamSequenceSerializer {
+            serializer: self,
+            byte_count: 0,
+        })
+    }
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        Ok(StreamStructSerializer {
+            serializer: self,
+            byte_count: 0,
+        })
+    }
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        Ok(StreamStructSerializer {
+            serializer: self,
+            byte_count: 0,
+        })
+    }
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        let tag = variant_tag(variant_index)?.serialize(&mut *self)?;
+        Ok(StreamStructSerializer {
+            serializer: self,
+            byte_count: tag,
+        })
+    }
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        require_extended(self.extended, "map")?;
+        self.serialize_seq(len)
+    }
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        Ok(StreamStructSerializer {
+            serializer: self,
+            byte_count: 0,
+        })
+    }
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        let tag = variant_tag(variant_index)?.serialize(&mut *self)?;
+        Ok(StreamStructSerializer {
+            serializer: self,
+            byte_count: tag,
+        })
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+/// A sequence serializer whose 2-byte count was already written.
+/// Maps are serialized the same way, their count being of key/value pairs.
+#[derive(Debug)]
+pub struct StreamSequenceSerializer<'ser, W: 'ser + Write> {
+    serializer: &'ser mut StreamSerializer<W>,
+    byte_count: u32,
+}
+
+impl<'ser, W: 'ser + Write> SerializeSeq for StreamSequenceSerializer<'ser, W> {
+    type Ok = u32;
+    type Error = SerErrorWithIo;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        let amt = value.serialize(&mut *self.serializer)?;
+        self.byte_count = self.byte_count.checked_add(amt).ok_or(SerError::TooBig)?;
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(self.byte_count.checked_add(2).ok_or(SerError::TooBig)?)
+    }
+}
+
+impl<'ser, W: 'ser + Write> SerializeMap for StreamSequenceSerializer<'ser, W> {
+    type Ok = u32;
+    type Error = SerErrorWithIo;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        SerializeSeq::serialize_element(self, key)
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        SerializeSeq::end(self)
+    }
+}
+
+/// A struct serializer that counts the byte size of everything serialized so far.
+#[derive(Debug)]
+pub struct StreamStructSerializer<'ser, W: 'ser + Write> {
+    serializer: &'ser mut StreamSerializer<W>,
+    byte_count: u32,
+}
+
+impl<'ser, W: 'ser + Write> SerializeStruct for StreamStructSerializer<'ser, W> {
+    type Ok = u32;
+    type Error = SerErrorWithIo;
+    fn serialize_field<T>(&mut self, _key: &'static str, value: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        SerializeTuple::serialize_element(self, value)
+    }
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        SerializeTuple::end(self)
+    }
+}
+
+impl<'ser, W: 'ser + Write> SerializeTuple for StreamStructSerializer<'ser, W> {
+    type Ok = u32;
+    type Error = SerErrorWithIo;
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        let amount = value.serialize(&mut *self.serializer)?;
+        self.byte_count = self
+            .byte_count
+            .checked_add(amount)
+            .ok_or(SerError::TooBig)?;
+        Ok(())
+    }
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(self.byte_count)
+    }
+}
+
+impl<'ser, W: 'ser + Write> SerializeTupleStruct for StreamStructSerializer<'ser, W> {
+    type Ok = u32;
+    type Error = SerErrorWithIo;
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        SerializeTuple::serialize_element(self, value)
+    }
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        SerializeTuple::end(self)
+    }
+}
+
+impl<'ser, W: 'ser + Write> SerializeTupleVariant for StreamStructSerializer<'ser, W> {
+    type Ok = u32;
+    type Error = SerErrorWithIo;
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        SerializeTuple::serialize_element(self, value)
+    }
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        SerializeTuple::end(self)
+    }
+}
+
+impl<'ser, W: 'ser + Write> SerializeStructVariant for StreamStructSerializer<'ser, W> {
+    type Ok = u32;
+    type Error = SerErrorWithIo;
+    fn serialize_field<T>(&mut self, _key: &'static str, value: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        SerializeTuple::serialize_element(self, value)
+    }
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        SerializeTuple::end(self)
+    }
+}
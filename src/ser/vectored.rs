@@ -0,0 +1,163 @@
+use super::bytes::BytesSerializer;
+use super::common::*;
+use super::frame::{header_len, EncoderConfig};
+use crate::message::{MessageTypeId, Payload};
+use ::bytes::{BufMut, BytesMut};
+use std::io::{self, IoSlice, Write};
+
+/// A whole message frame, split into the header and small fields,
+/// which are copied into a scratch buffer, and the payload,
+/// which is borrowed from the message.
+///
+/// Its slices can be written all at once with `Write::write_vectored`.
+#[derive(Debug, Clone)]
+pub struct VectoredFrame<'a> {
+    head: BytesMut,
+    payload: &'a [u8],
+}
+
+impl<'a> VectoredFrame<'a> {
+    /// The frame's header and every field before the payload.
+    pub fn head(&self) -> &[u8] {
+        &self.head
+    }
+
+    /// The payload, borrowed from the message.
+    pub fn payload(&self) -> &'a [u8] {
+        self.payload
+    }
+
+    /// The size of the whole frame, header included.
+    pub fn len(&self) -> usize {
+        self.head.len() + self.payload.len()
+    }
+
+    /// Whether the frame is empty, which it never is.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The frame as slices to pass to `Write::write_vectored`.
+    pub fn io_slices(&self) -> [IoSlice<'_>; 2] {
+        [IoSlice::new(&self.head), IoSlice::new(self.payload)]
+    }
+
+    /// Write the whole frame to the given writer with as few calls to
+    /// `Write::write_vectored` as it allows.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let mut written = 0;
+        while written < self.len() {
+            let res = if written < self.head.len() {
+                writer.write_vectored(&[
+                    IoSlice::new(&self.head[written..]),
+                    IoSlice::new(self.payload),
+                ])
+            } else {
+                writer.write(&self.payload[written - self.head.len()..])
+            };
+            match res {
+                Ok(0) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::WriteZero,
+                        "failed to write whole frame",
+                    ))
+                }
+                Ok(amount) => written += amount,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Serialize the given message as a whole frame without copying its payload.
+/// ```
+/// # use nine::ser::*;
+/// use nine::p2000::Rread;
+/// let rread = Rread { tag: 1, data: vec![0xAA; 4096] };
+/// let frame = into_vectored(&rread).unwrap();
+/// assert_eq!(frame.head().len(), 4 + 1 + 2 + 4);
+/// assert_eq!(frame.len(), into_frame(&rread).unwrap().len());
+/// ```
+pub fn into_vectored<T>(t: &T) -> Result<VectoredFrame<'_>, SerError>
+where
+    T: Serialize + MessageTypeId + Payload,
+{
+    into_vectored_with(t, &EncoderConfig::default())
+}
+
+/// Serialize the given message as a whole frame without copying its payload,
+/// using the given configuration.
+///
+/// Fails if the payload isn't the message's last field and only byte array.
+pub fn into_vectored_with<'a, T>(
+    t: &'a T,
+    config: &EncoderConfig,
+) -> Result<VectoredFrame<'a>, SerError>
+where
+    T: Serialize + MessageTypeId + Payload,
+{
+    let payload = t.payload();
+    let mut head = BytesMut::with_capacity(64);
+    head.put_u32_le(0);
+    if !t.serializes_type_id() {
+        head.put_u8(t.msg_type_id());
+    }
+
+    let mut ser = BytesSerializer::eliding_bytes(&mut head);
+    if config.extended {
+        ser = ser.extended();
+    }
+    let body_size = t.serialize(&mut ser)?;
+    match ser.elided() {
+        Some((amount, _)) if amount == 0 && payload.is_empty() => (),
+        Some((amount, end)) if amount as usize == payload.len() && end == head.len() => (),
+        _ => {
+            return Err(SerError::Unsupported(
+                "payload must be the message's last field and only byte array",
+            ))
+        }
+    }
+
+    let size = body_size
+        .checked_add(header_len(t))
+        .ok_or(SerError::TooBig)?;
+    if size > config.msize {
+        return Err(SerError::ExceedsMsize {
+            size,
+            msize: config.msize,
+        });
+    }
+    head[..4].copy_from_slice(&size.to_le_bytes());
+
+    Ok(VectoredFrame { head, payload })
+}
+
+/// Serializes the given message as a whole frame and writes it to the given writer
+/// with `Write::write_vectored`, without copying its payload.
+///
+/// Returns the size of the frame, which is the number of bytes written.
+pub fn write_message_vectored<T, W>(t: &T, writer: W) -> Result<u32, SerErrorWithIo>
+where
+    T: Serialize + MessageTypeId + Payload,
+    W: Write,
+{
+    write_message_vectored_with(t, writer, &EncoderConfig::default())
+}
+
+/// Serializes the given message as a whole frame and writes it to the given writer
+/// with `Write::write_vectored`, using the given configuration.
+pub fn write_message_vectored_with<T, W>(
+    t: &T,
+    writer: W,
+    config: &EncoderConfig,
+) -> Result<u32, SerErrorWithIo>
+where
+    T: Serialize + MessageTypeId + Payload,
+    W: Write,
+{
+    let frame = into_vectored_with(t, config)?;
+    frame.write_to(writer)?;
+    Ok(frame.len() as u32)
+}
@@ -2,16 +2,14 @@ use super::common::*;
 use byteorder::{WriteBytesExt, LittleEndian};
 use serde::ser::*;
 use std::io::{self, Seek, SeekFrom, Write};
+use std::mem;
 
 /// Serializes the given item into the given type that implements write and seek.
 /// This is typically a file or a buffer (`io::Cursor<Vec<u8>>`).
 ///
 /// Returns the number of bytes written.
 pub fn into_write_seeker<T: Serialize, W: Write + Seek>(t: &T, writer: W) -> Result<u32, SerErrorWithIo> {
-    let mut ser = WriteSerializer {
-        writer,
-        in_stat: false,
-    };
+    let mut ser = WriteSerializer::new(writer);
 
     t.serialize(&mut ser)
 }
@@ -20,7 +18,10 @@ pub fn into_write_seeker<T: Serialize, W: Write + Seek>(t: &T, writer: W) -> Res
 #[derive(Debug)]
 pub struct WriteSerializer<W: Write + Seek> {
     pub writer: W,
-    pub(crate) in_stat: bool, // if in a rstat/twstat message, double-prefix the size
+    extended: bool,
+    /// Whether the next byte array is written raw, with no length prefix,
+    /// as it is directly inside a `RAW_BYTES` newtype struct.
+    raw: bool,
 }
 
 impl<W: Write + Seek> WriteSerializer<W> {
@@ -28,7 +29,15 @@ impl<W: Write + Seek> WriteSerializer<W> {
     pub fn new(writer: W) -> WriteSerializer<W> {
         WriteSerializer {
             writer,
-            in_stat: false,
+            extended: false,
+            raw: false,
+        }
+    }
+    /// Write the [extended encodings](crate::ser#extended-encodings).
+    pub fn extended(self) -> WriteSerializer<W> {
+        WriteSerializer {
+            extended: true,
+            ..self
         }
     }
     /// Consume the serializer, giving back the writer.
@@ -54,10 +63,10 @@ impl<'ser, W: 'ser + Write + Seek> Serializer for &'ser mut WriteSerializer<W> {
     type SerializeSeq = CountingSequenceSerializer<'ser, W>;
     type SerializeTuple = AccountingStructSerializer<'ser, W>;
     type SerializeTupleStruct = AccountingStructSerializer<'ser, W>;
-    type SerializeTupleVariant = Unimplemented;
-    type SerializeMap = Unimplemented;
+    type SerializeTupleVariant = AccountingStructSerializer<'ser, W>;
+    type SerializeMap = CountingSequenceSerializer<'ser, W>;
     type SerializeStruct = AccountingStructSerializer<'ser, W>;
-    type SerializeStructVariant = Unimplemented;
+    type SerializeStructVariant = AccountingStructSerializer<'ser, W>;
 
     fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
         self.writer.write_u8(v as u8)?;
@@ -101,8 +110,12 @@ impl<'ser, W: 'ser + Write + Seek> Serializer for &'ser mut WriteSerializer<W> {
     fn serialize_f64(self, _v: f64) -> Result<Self::Ok, Self::Error> {
         Err(SerError::UnspecifiedType("f64").into())
     }
-    fn serialize_char(self, _v: char) -> Result<Self::Ok, Self::Error> {
-        Err(SerError::UnspecifiedType("char").into())
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
+        require_extended(self.extended, "char")?;
+        let mut buf = [0u8; 4];
+        let encoded = v.encode_utf8(&mut buf);
+        self.writer.write_all(encoded.as_bytes())?;
+        Ok(encoded.len() as u32)
     }
     fn serialize_str(self, s: &str) -> Result<Self::Ok, Self::Error> {
         if s.len() > u16::MAX as usize {
@@ -115,6 +128,11 @@ impl<'ser, W: 'ser + Write + Seek> Serializer for &'ser mut WriteSerializer<W> {
         Ok(len as u32 + 2)
     }
     fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
+        if mem::replace(&mut self.raw, false) {
+            let len = raw_len(v)?;
+            self.writer.write_all(v)?;
+            return Ok(len);
+        }
         if v.len() > BYTES_LEN_MAX as usize {
             return Err(SerError::BytesTooLong.into());
         }
@@ -123,13 +141,17 @@ impl<'ser, W: 'ser + Write + Seek> Serializer for &'ser mut WriteSerializer<W> {
         Ok(v.len() as u32 + 4)
     }
     fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
-        Err(SerError::UnspecifiedType("none").into())
+        require_extended(self.extended, "none")?;
+        0u8.serialize(self)
     }
-    fn serialize_some<T: ?Sized>(self, _value: &T) -> Result<Self::Ok, Self::Error>
+    fn serialize_some<T: ?Sized>(self, value: &T) -> Result<Self::Ok, Self::Error>
     where
         T: Serialize,
     {
-        Err(SerError::UnspecifiedType("some").into())
+        require_extended(self.extended, "some")?;
+        let presence = 1u8.serialize(&mut *self)?;
+        let amount = value.serialize(self)?;
+        Ok(amount.checked_add(presence).ok_or(SerError::TooBig)?)
     }
     fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
         Err(SerError::UnspecifiedType("unit").into())
@@ -140,32 +162,51 @@ impl<'ser, W: 'ser + Write + Seek> Serializer for &'ser mut WriteSerializer<W> {
     fn serialize_unit_variant(
         self,
         _name: &'static str,
-        _variant_index: u32,
+        variant_index: u32,
         _variant: &'static str,
     ) -> Result<Self::Ok, Self::Error> {
-        Err(SerError::UnspecifiedType("unit variant").into())
+        variant_tag(variant_index)?.serialize(self)
     }
     fn serialize_newtype_struct<T: ?Sized>(
         self,
-        _name: &'static str,
+        name: &'static str,
         value: &T,
     ) -> Result<Self::Ok, Self::Error>
     where
         T: Serialize,
     {
-        value.serialize(self)
+        if name == RAW_BYTES {
+            self.raw = true;
+            let res = value.serialize(&mut *self);
+            self.raw = false;
+            return res;
+        }
+        if name != SIZE_PREFIXED {
+            return value.serialize(self);
+        }
+
+        self.seek_fwd(2)?;
+        let amount = value.serialize(&mut *self)?;
+        let size = prefixed_size(amount)?;
+        self.seek_back(amount)?;
+        self.seek_back(2)?;
+        size.serialize(&mut *self)?;
+        self.seek_fwd(amount)?;
+        Ok(amount + 2)
     }
     fn serialize_newtype_variant<T: ?Sized>(
         self,
         _name: &'static str,
-        _variant_index: u32,
+        variant_index: u32,
         _variant: &'static str,
-        _value: &T,
+        value: &T,
     ) -> Result<Self::Ok, Self::Error>
     where
         T: Serialize,
     {
-        Err(SerError::UnspecifiedType("newtype variant").into())
+        let tag = variant_tag(variant_index)?.serialize(&mut *self)?;
+        let amount = value.serialize(self)?;
+        Ok(amount.checked_add(tag).ok_or(SerError::TooBig)?)
     }
     fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
         if let Some(len) = len {
@@ -185,7 +226,6 @@ impl<'ser, W: 'ser + Write + Seek> Serializer for &'ser mut WriteSerializer<W> {
         Ok(AccountingStructSerializer {
             serializer: self,
             byte_count: 0,
-            size_behavior: StructSizeBehavior::None,
         })
     }
     fn serialize_tuple_struct(
@@ -196,55 +236,47 @@ impl<'ser, W: 'ser + Write + Seek> Serializer for &'ser mut WriteSerializer<W> {
         Ok(AccountingStructSerializer {
             serializer: self,
             byte_count: 0,
-            size_behavior: StructSizeBehavior::None,
         })
     }
     fn serialize_tuple_variant(
         self,
         _name: &'static str,
-        _variant_index: u32,
+        variant_index: u32,
         _variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeTupleVariant, Self::Error> {
-        Err(SerError::UnspecifiedType("tuple variant").into())
+        let tag = variant_tag(variant_index)?.serialize(&mut *self)?;
+        Ok(AccountingStructSerializer {
+            serializer: self,
+            byte_count: tag,
+        })
     }
-    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
-        Err(SerError::UnspecifiedType("map").into())
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        require_extended(self.extended, "map")?;
+        self.serialize_seq(len)
     }
     fn serialize_struct(
         self,
-        name: &'static str,
+        _name: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeStruct, Self::Error> {
-        let size_behavior = if name == "Twstat" || name == "Rstat" {
-            self.in_stat = true;
-            StructSizeBehavior::None
-        } else if name == "Stat" {
-            if self.in_stat {
-                self.seek_fwd(4)?;
-                StructSizeBehavior::DoubleTwo
-            } else {
-                self.seek_fwd(2)?;
-                StructSizeBehavior::Two
-            }
-        } else {
-            StructSizeBehavior::None
-        };
-
         Ok(AccountingStructSerializer {
             serializer: self,
             byte_count: 0,
-            size_behavior,
         })
     }
     fn serialize_struct_variant(
         self,
         _name: &'static str,
-        _variant_index: u32,
+        variant_index: u32,
         _variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeStructVariant, Self::Error> {
-        Err(SerError::UnspecifiedType("struct_variant").into())
+        let tag = variant_tag(variant_index)?.serialize(&mut *self)?;
+        Ok(AccountingStructSerializer {
+            serializer: self,
+            byte_count: tag,
+        })
     }
 
     fn is_human_readable(&self) -> bool {
@@ -254,6 +286,7 @@ impl<'ser, W: 'ser + Write + Seek> Serializer for &'ser mut WriteSerializer<W> {
 
 /// A sequence serializer that counts how many items it
 /// gets and then prefixes with the 2-byte count.
+/// Maps are serialized the same way, counting key/value pairs.
 #[derive(Debug)]
 pub struct CountingSequenceSerializer<'ser, W: 'ser + Write + Seek> {
     serializer: &'ser mut WriteSerializer<W>,
@@ -289,12 +322,36 @@ impl<'ser, W: 'ser + Write + Seek> SerializeSeq for CountingSequenceSerializer<'
     }
 }
 
+impl<'ser, W: 'ser + Write + Seek> SerializeMap for CountingSequenceSerializer<'ser, W> {
+    type Ok = u32;
+    type Error = SerErrorWithIo;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        SerializeSeq::serialize_element(self, key)
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        let amt = value.serialize(&mut *self.serializer)?;
+        self.byte_count = self.byte_count.checked_add(amt).ok_or(SerError::TooBig)?;
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        SerializeSeq::end(self)
+    }
+}
+
 /// A struct serializer that counts the byte size of everything serialized so far.
 #[derive(Debug)]
 pub struct AccountingStructSerializer<'ser, W: 'ser + Write + Seek> {
     serializer: &'ser mut WriteSerializer<W>,
     byte_count: u32,
-    size_behavior: StructSizeBehavior,
 }
 
 impl<'ser, W: 'ser + Write + Seek> SerializeStruct for AccountingStructSerializer<'ser, W> {
@@ -315,9 +372,6 @@ impl<'ser, W: 'ser + Write + Seek> SerializeStruct for AccountingStructSerialize
     }
 }
 
-const STRUCT_SIZE_TWO_MAX: u32 = u16::MAX as u32 - 2;
-const STRUCT_SIZE_DOUBLE_TWO_MAX: u32 = u16::MAX as u32 - 4;
-
 impl<'ser, W: 'ser + Write + Seek> SerializeTuple for AccountingStructSerializer<'ser, W> {
     type Ok = u32;
     type Error = SerErrorWithIo;
@@ -330,37 +384,10 @@ impl<'ser, W: 'ser + Write + Seek> SerializeTuple for AccountingStructSerializer
             .byte_count
             .checked_add(amount)
             .ok_or(SerError::TooBig)?;
-
-        // TODO: is it quicker to check during end instead?
-        match self.size_behavior {
-            StructSizeBehavior::Two if self.byte_count > STRUCT_SIZE_TWO_MAX => {
-                Err(SerError::TooBig.into())
-            }
-            StructSizeBehavior::DoubleTwo if self.byte_count > STRUCT_SIZE_DOUBLE_TWO_MAX => {
-                Err(SerError::TooBig.into())
-            }
-            _ => Ok(()),
-        }
+        Ok(())
     }
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        Ok(match self.size_behavior {
-            StructSizeBehavior::None => self.byte_count,
-            StructSizeBehavior::Two => {
-                self.serializer.seek_back(self.byte_count)?;
-                self.serializer.seek_back(2)?;
-                (self.byte_count as u16).serialize(&mut *self.serializer)?;
-                self.serializer.seek_fwd(self.byte_count)?;
-                self.byte_count + 2
-            }
-            StructSizeBehavior::DoubleTwo => {
-                self.serializer.seek_back(self.byte_count)?;
-                self.serializer.seek_back(4)?;
-                (self.byte_count as u16 + 2).serialize(&mut *self.serializer)?;
-                (self.byte_count as u16 + 0).serialize(&mut *self.serializer)?;
-                self.serializer.seek_fwd(self.byte_count)?;
-                self.byte_count + 4
-            }
-        })
+        Ok(self.byte_count)
     }
 }
 
@@ -378,53 +405,53 @@ impl<'ser, W: 'ser + Write + Seek> SerializeTupleStruct for AccountingStructSeri
     }
 }
 
-//region Unimplemented
-/// Stand-in code for types of serialization that will never happen
-/// because the types are unspecified.
-pub enum Unimplemented {}
-
-impl SerializeMap for Unimplemented {
+impl<'ser, W: 'ser + Write + Seek> SerializeTupleVariant for AccountingStructSerializer<'ser, W> {
     type Ok = u32;
     type Error = SerErrorWithIo;
-    fn serialize_key<T: ?Sized>(&mut self, _key: &T) -> Result<(), Self::Error>
-    where
-        T: Serialize,
-    {
-        unreachable!()
-    }
-    fn serialize_value<T: ?Sized>(&mut self, _value: &T) -> Result<(), Self::Error>
+    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
     where
         T: Serialize,
     {
-        unreachable!()
+        SerializeTuple::serialize_element(self, value)
     }
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        unreachable!()
+        SerializeTuple::end(self)
     }
 }
 
-impl SerializeTupleVariant for Unimplemented {
+impl<'ser, W: 'ser + Write + Seek> SerializeStructVariant for AccountingStructSerializer<'ser, W> {
     type Ok = u32;
     type Error = SerErrorWithIo;
-    fn serialize_field<T: ?Sized>(&mut self, _value: &T) -> Result<(), Self::Error>
+    fn serialize_field<T: ?Sized>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error>
     where
         T: Serialize,
     {
-        unreachable!()
+        SerializeTuple::serialize_element(self, value)
     }
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        unreachable!()
+        SerializeTuple::end(self)
     }
 }
 
-impl SerializeStructVariant for Unimplemented {
+//region Unimplemented
+/// Stand-in code for types of serialization that will never happen
+/// because the types are unspecified.
+pub enum Unimplemented {}
+
+impl SerializeMap for Unimplemented {
     type Ok = u32;
     type Error = SerErrorWithIo;
-    fn serialize_field<T: ?Sized>(
-        &mut self,
-        _key: &'static str,
-        _value: &T,
-    ) -> Result<(), Self::Error>
+    fn serialize_key<T: ?Sized>(&mut self, _key: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize,
+    {
+        unreachable!()
+    }
+    fn serialize_value<T: ?Sized>(&mut self, _value: &T) -> Result<(), Self::Error>
     where
         T: Serialize,
     {
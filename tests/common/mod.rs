@@ -0,0 +1,42 @@
+//! Helpers shared by the test crates, each of which uses only some of them.
+#![allow(dead_code)]
+
+use nine::de::*;
+use nine::message::{MessageSet, MessageTypeId};
+use nine::p2000::{FileMode, FileType, Qid, Stat};
+use nine::ser::*;
+use std::fmt::Debug;
+
+/// Check that the message serializes to exactly the given frame,
+/// and that the frame decodes back to it as part of the message set `M`.
+pub fn golden<M, T>(msg: T, frame: &[u8])
+where
+    M: MessageSet + Serialize + MessageTypeId + PartialEq + Debug,
+    T: Serialize + MessageTypeId + Into<M> + Debug,
+{
+    assert_eq!(into_frame(&msg).unwrap(), frame, "serializing {:?}", msg);
+    let msg: M = msg.into();
+    assert_eq!(from_frame::<M, _>(frame).unwrap(), msg);
+    assert_eq!(into_frame(&msg).unwrap(), frame, "serializing {:?}", msg);
+}
+
+/// A 9P2000 stat of a plain file, for messages that need one.
+pub fn stat() -> Stat {
+    Stat {
+        type_: 1,
+        dev: 2,
+        qid: Qid {
+            file_type: FileType::FILE,
+            version: 3,
+            path: 4,
+        },
+        mode: FileMode::OWNER_READ | FileMode::OWNER_WRITE,
+        atime: 5,
+        mtime: 6,
+        length: 512,
+        name: "hello".into(),
+        uid: "glenda".into(),
+        gid: "glenda".into(),
+        muid: "glenda".into(),
+    }
+}
@@ -0,0 +1,760 @@
+//! Tests relating to whole message frames.
+
+extern crate byteorder;
+extern crate nine;
+
+mod common;
+
+use crate::common::{golden, stat};
+use byteorder::{WriteBytesExt, LE};
+use nine::de::*;
+use nine::p2000::*;
+use nine::ser::*;
+
+fn rclunk_frame() -> Vec<u8> {
+    let mut frame = Vec::new();
+    frame.write_u32::<LE>(7).unwrap();
+    frame.write_u8(121).unwrap();
+    frame.write_u16::<LE>(3).unwrap();
+    frame
+}
+
+#[test]
+fn read_message_dispatches_on_type() {
+    let mut frame = Vec::new();
+    frame.write_u32::<LE>(4 + 1 + 2 + 4 + 2 + 6).unwrap();
+    frame.write_u8(100).unwrap();
+    frame.write_u16::<LE>(NOTAG).unwrap();
+    frame.write_u32::<LE>(8192).unwrap();
+    frame.write_u16::<LE>(6).unwrap();
+    frame.extend_from_slice(b"9P2000");
+
+    let actual: Message = read_message(&frame[..]).unwrap();
+    let expected = Message::Tversion(Tversion {
+        tag: NOTAG,
+        msize: 8192,
+        version: "9P2000".into(),
+    });
+
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn from_frame_rclunk() {
+    let actual: Message = from_frame(rclunk_frame()).unwrap();
+
+    assert_eq!(actual, Message::Rclunk(Rclunk { tag: 3 }));
+}
+
+#[test]
+fn unknown_message_type() {
+    let mut frame = rclunk_frame();
+    frame[4] = 106; // Terror is illegal
+
+    match from_frame::<Message, _>(frame) {
+        Err(DeError::UnknownMessageType(106)) => (),
+        other => panic!("expected unknown type error, got {:?}", other),
+    }
+}
+
+#[test]
+fn size_mismatch() {
+    let mut frame = rclunk_frame();
+    frame.push(0);
+
+    match from_frame::<Message, _>(frame) {
+        Err(DeError::SizeMismatch { size: 7, actual: 8 }) => (),
+        other => panic!("expected size mismatch, got {:?}", other),
+    }
+}
+
+#[test]
+fn frame_too_small() {
+    let frame = [4u8, 0, 0, 0, 121];
+
+    match read_message::<Message, _>(&frame[..]) {
+        Err(DeError::FrameTooSmall(4)) => (),
+        other => panic!("expected frame too small, got {:?}", other),
+    }
+}
+
+#[test]
+fn into_frame_rclunk() {
+    let frame = into_frame(&Rclunk { tag: 3 }).unwrap();
+
+    assert_eq!(frame, rclunk_frame());
+}
+
+#[test]
+fn message_frame_round_trip() {
+    let msg = Message::Twalk(Twalk {
+        tag: 1,
+        fid: 2,
+        newfid: 3,
+        wname: vec!["one".into(), "two".into()],
+    });
+
+    let frame = into_frame(&msg).unwrap();
+    assert_eq!(frame.len(), 4 + 1 + 2 + 4 + 4 + 2 + 5 + 5);
+    assert_eq!(frame[4], 110);
+
+    let actual: Message = from_frame(&frame).unwrap();
+    assert_eq!(actual, msg);
+}
+
+#[test]
+fn write_message_streams_frame() {
+    let msg = Message::Twalk(Twalk {
+        tag: 1,
+        fid: 2,
+        newfid: 3,
+        wname: vec!["one".into(), "two".into()],
+    });
+    let mut out = Vec::new();
+
+    let size = write_message(&msg, &mut out).unwrap();
+
+    assert_eq!(size as usize, out.len());
+    assert_eq!(out, into_frame(&msg).unwrap());
+}
+
+#[test]
+fn serialized_frame_size_matches_frame() {
+    let twalk = Twalk {
+        tag: 1,
+        fid: 2,
+        newfid: 3,
+        wname: vec!["one".into(), "two".into()],
+    };
+    let body_size = serialized_size(&twalk).unwrap();
+    let msg = Message::Twalk(twalk);
+
+    let size = serialized_frame_size(&msg).unwrap();
+
+    assert_eq!(size as usize, into_frame(&msg).unwrap().len());
+    assert_eq!(size, body_size + 5);
+    // A message set serializes its type ID itself.
+    assert_eq!(size, serialized_size(&msg).unwrap() + 4);
+}
+
+/// A writer that takes at most a few bytes per call, even when vectored.
+struct Trickle(Vec<u8>);
+
+impl std::io::Write for Trickle {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let amount = buf.len().min(3);
+        self.0.extend_from_slice(&buf[..amount]);
+        Ok(amount)
+    }
+    fn write_vectored(&mut self, bufs: &[std::io::IoSlice<'_>]) -> std::io::Result<usize> {
+        // Take from as many slices as fit, so writes can end partway through any of them.
+        let mut amount = 0;
+        for buf in bufs {
+            amount += self.write(&buf[..buf.len().min(3 - amount)])?;
+        }
+        Ok(amount)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn vectored_frame_borrows_payload() {
+    let twrite = Message::Twrite(Twrite {
+        tag: 1,
+        fid: 2,
+        offset: 3,
+        data: b"some file contents".to_vec(),
+    });
+
+    let frame = into_vectored(&twrite).unwrap();
+    assert_eq!(frame.head().len(), 4 + 1 + 2 + 4 + 8 + 4);
+    assert_eq!(frame.payload().as_ptr(), twrite.payload().as_ptr());
+
+    let mut out = Trickle(Vec::new());
+    let size = write_message_vectored(&twrite, &mut out).unwrap();
+    assert_eq!(size as usize, out.0.len());
+    assert_eq!(out.0, into_frame(&twrite).unwrap());
+
+    let rclunk = Message::Rclunk(Rclunk { tag: 3 });
+    let frame = into_vectored(&rclunk).unwrap();
+    assert_eq!(frame.head(), &rclunk_frame()[..]);
+    assert!(frame.payload().is_empty());
+}
+
+#[test]
+fn vectored_frame_needs_trailing_payload() {
+    #[derive(serde::Serialize)]
+    struct Backwards {
+        #[serde(serialize_with = "serialize_bytes")]
+        data: Vec<u8>,
+        tag: u16,
+    }
+    impl MessageTypeId for Backwards {
+        fn msg_type_id(&self) -> u8 {
+            0
+        }
+    }
+    impl Payload for Backwards {
+        fn payload(&self) -> &[u8] {
+            &self.data
+        }
+    }
+
+    let backwards = Backwards {
+        data: vec![1, 2, 3],
+        tag: 1,
+    };
+    match into_vectored(&backwards) {
+        Err(SerError::Unsupported(_)) => (),
+        other => panic!("expected unsupported, got {:?}", other.map(|f| f.len())),
+    }
+}
+
+#[test]
+fn append_frame_after_existing() {
+    let mut buf = rclunk_frame();
+    let size = append_frame(&Message::from(Rclunk { tag: 4 }), &mut buf).unwrap();
+
+    assert_eq!(size, 7);
+    assert_eq!(&buf[7..], [7, 0, 0, 0, 121, 4, 0]);
+}
+
+#[test]
+fn decoder_byte_at_a_time() {
+    let first = Message::Rclunk(Rclunk { tag: 1 });
+    let second = Message::Rread(Rread {
+        tag: 2,
+        data: b"hello".to_vec(),
+    });
+    let mut stream = into_frame(&first).unwrap();
+    stream.extend(into_frame(&second).unwrap());
+
+    let mut decoder = FrameDecoder::<Message>::new();
+    let mut decoded = Vec::new();
+    for byte in stream {
+        decoder.feed(&[byte]);
+        match decoder.decode().unwrap() {
+            Decoded::Message(msg) => decoded.push(msg),
+            Decoded::NeedMore(amount) => assert!(amount > 0),
+        }
+    }
+
+    assert_eq!(decoded, vec![first, second]);
+    assert_eq!(decoder.buffered(), 0);
+}
+
+#[test]
+fn decoder_needs_rest_of_frame() {
+    let mut decoder = FrameDecoder::<Message>::new();
+    assert_eq!(decoder.decode().unwrap(), Decoded::NeedMore(4));
+
+    let frame = into_frame(&Tclunk { tag: 1, fid: 2 }).unwrap();
+    decoder.feed(&frame[..6]);
+    assert_eq!(
+        decoder.decode().unwrap(),
+        Decoded::NeedMore(frame.len() - 6)
+    );
+    assert_eq!(decoder.buffered(), 6);
+}
+
+#[test]
+fn decoder_skips_unknown_frame() {
+    let mut decoder = FrameDecoder::<Message>::new();
+    let mut unknown = rclunk_frame();
+    unknown[4] = 106;
+    decoder.feed(&unknown);
+    decoder.feed(&rclunk_frame());
+
+    match decoder.decode() {
+        Err(DeError::UnknownMessageType(106)) => (),
+        other => panic!("expected unknown type error, got {:?}", other),
+    }
+    assert_eq!(
+        decoder.decode().unwrap(),
+        Decoded::Message(Message::Rclunk(Rclunk { tag: 3 }))
+    );
+}
+
+#[test]
+fn encoder_rejects_oversized_frame() {
+    let config = EncoderConfig::with_msize(16);
+    let mut buf = rclunk_frame();
+    let rread = Rread {
+        tag: 1,
+        data: vec![0; 16],
+    };
+
+    match append_frame_with(&rread, &mut buf, &config) {
+        Err(SerError::ExceedsMsize {
+            size: 27,
+            msize: 16,
+        }) => (),
+        other => panic!("expected exceeds msize, got {:?}", other),
+    }
+    assert_eq!(buf, rclunk_frame());
+
+    append_frame_with(&Rclunk { tag: 3 }, &mut buf, &config).unwrap();
+}
+
+#[test]
+fn decoders_refuse_oversized_frame() {
+    let config = DecoderConfig::with_msize(8192);
+    let mut header = Vec::new();
+    header.write_u32::<LE>(u32::max_value()).unwrap();
+    header.write_u8(117).unwrap();
+
+    match read_message_with::<Message, _>(&header[..], &config) {
+        Err(DeError::ExceedsMsize { size, msize: 8192 }) => assert_eq!(size, u32::max_value()),
+        other => panic!("expected exceeds msize, got {:?}", other),
+    }
+
+    let mut decoder = FrameDecoder::<Message>::with_config(config);
+    decoder.feed(&header[..4]);
+    assert!(decoder.decode().is_err());
+    assert_eq!(decoder.buffered(), 4);
+}
+
+#[test]
+fn decoders_enforce_limits() {
+    let frame = into_frame(&Twalk {
+        tag: 1,
+        fid: 2,
+        newfid: 3,
+        wname: vec!["one".into(), "two".into()],
+    })
+    .unwrap();
+
+    for (limits, kind) in [
+        (
+            Limits {
+                str_len: 2,
+                ..Limits::default()
+            },
+            "string",
+        ),
+        (
+            Limits {
+                seq_len: 1,
+                ..Limits::default()
+            },
+            "sequence",
+        ),
+    ] {
+        let config = DecoderConfig {
+            limits,
+            ..DecoderConfig::default()
+        };
+        let check = |err: DeError| match err.root() {
+            DeError::LimitExceeded { kind: k, .. } if *k == kind => (),
+            other => panic!("expected {} limit exceeded, got {:?}", kind, other),
+        };
+
+        check(read_message_with::<Message, _>(&frame[..], &config).unwrap_err());
+        check(from_frame_with::<Message, _>(&frame, &config).unwrap_err());
+
+        let mut decoder = FrameDecoder::<Message>::with_config(config);
+        decoder.feed(&frame);
+        check(decoder.decode().unwrap_err());
+        assert_eq!(decoder.buffered(), 0);
+    }
+}
+
+fn strict() -> DecoderConfig {
+    DecoderConfig {
+        strict: true,
+        ..DecoderConfig::default()
+    }
+}
+
+/// An rclunk frame with trailing garbage, followed by a normal one.
+fn padded_stream() -> Vec<u8> {
+    let mut stream = Vec::new();
+    stream.write_u32::<LE>(10).unwrap();
+    stream.write_u8(121).unwrap();
+    stream.write_u16::<LE>(1).unwrap();
+    stream.extend_from_slice(&[0xAA, 0xBB, 0xCC]);
+    stream.extend(rclunk_frame());
+    stream
+}
+
+#[test]
+fn lenient_skips_trailing_bytes() {
+    let stream = padded_stream();
+    let mut reader = &stream[..];
+
+    let first: Message = read_message(&mut reader).unwrap();
+    let second: Message = read_message(&mut reader).unwrap();
+
+    assert_eq!(first, Message::Rclunk(Rclunk { tag: 1 }));
+    assert_eq!(second, Message::Rclunk(Rclunk { tag: 3 }));
+    assert!(reader.is_empty());
+}
+
+#[test]
+fn strict_rejects_trailing_bytes() {
+    let stream = padded_stream();
+    let mut reader = &stream[..];
+
+    match read_message_with::<Message, _>(&mut reader, &strict()) {
+        Err(DeError::TrailingBytes(3)) => (),
+        other => panic!("expected trailing bytes, got {:?}", other),
+    }
+    let second: Message = read_message_with(&mut reader, &strict()).unwrap();
+    assert_eq!(second, Message::Rclunk(Rclunk { tag: 3 }));
+
+    let mut decoder = FrameDecoder::<Message>::with_config(strict());
+    decoder.feed(&stream);
+    match decoder.decode() {
+        Err(DeError::TrailingBytes(3)) => (),
+        other => panic!("expected trailing bytes, got {:?}", other),
+    }
+    assert_eq!(
+        decoder.decode().unwrap(),
+        Decoded::Message(Message::Rclunk(Rclunk { tag: 3 }))
+    );
+}
+
+#[test]
+fn short_frame() {
+    let mut stream = Vec::new();
+    stream.write_u32::<LE>(9).unwrap();
+    stream.write_u8(120).unwrap(); // tclunk
+    stream.write_u16::<LE>(1).unwrap();
+    stream.write_u16::<LE>(2).unwrap(); // half of the fid
+    stream.extend(rclunk_frame());
+    let mut reader = &stream[..];
+
+    match read_message::<Message, _>(&mut reader) {
+        Err(DeError::ShortFrame(9)) => (),
+        other => panic!("expected short frame, got {:?}", other),
+    }
+    let second: Message = read_message(&mut reader).unwrap();
+    assert_eq!(second, Message::Rclunk(Rclunk { tag: 3 }));
+
+    let mut decoder = FrameDecoder::<Message>::new();
+    decoder.feed(&stream);
+    match decoder.decode() {
+        Err(DeError::ShortFrame(9)) => (),
+        other => panic!("expected short frame, got {:?}", other),
+    }
+}
+
+#[test]
+fn truncated_stream_is_eof() {
+    let frame = rclunk_frame();
+
+    let err = read_message::<Message, _>(&frame[..6]).unwrap_err();
+    assert!(err.is_eof());
+}
+
+#[test]
+fn error_context() {
+    let rstat = Rstat {
+        tag: 1,
+        stat: stat(),
+    };
+    let mut frame = into_frame(&rstat).unwrap();
+    // size, type, tag, both stat sizes, fixed stat fields, name
+    let uid_offset = 4 + 1 + 2 + 4 + 39 + 2 + rstat.stat.name.len();
+    frame[uid_offset + 2] = 0xFF;
+
+    let check = |err: DeError| {
+        let context = err.context().expect("error should have context");
+        assert_eq!(context.path(), "Rstat.stat.uid");
+        assert_eq!(context.offset, uid_offset as u64);
+        assert_eq!(context.msg_type, Some(125));
+        match err.root() {
+            DeError::Utf8(_) | DeError::Utf8Str(_) => (),
+            other => panic!("expected invalid UTF8, got {:?}", other),
+        }
+    };
+
+    check(from_frame::<Message, _>(&frame).unwrap_err());
+
+    let mut decoder = FrameDecoder::<Message>::new();
+    decoder.feed(&frame);
+    check(decoder.decode().unwrap_err());
+
+    let err = from_slice::<Rstat>(&frame[5..]).unwrap_err();
+    assert_eq!(err.context().unwrap().path(), "Rstat.stat.uid");
+    assert_eq!(err.context().unwrap().offset, uid_offset as u64 - 5);
+}
+
+#[test]
+fn stat_extension_is_skipped() {
+    let rstat = Rstat {
+        tag: 1,
+        stat: stat(),
+    };
+    let mut frame = into_frame(&rstat).unwrap();
+    // Like the extension string of a 9P2000.u stat.
+    frame.extend_from_slice(&[3, 0, b'e', b'x', b't']);
+    let bump = |bytes: &mut [u8], amount: u16| {
+        let value = u16::from_le_bytes([bytes[0], bytes[1]]).wrapping_add(amount);
+        bytes[..2].copy_from_slice(&value.to_le_bytes());
+    };
+    bump(&mut frame[0..], 5);
+    bump(&mut frame[7..], 5);
+    bump(&mut frame[9..], 5);
+
+    let strict = strict();
+    let actual: Message = from_frame_with(&frame, &strict).unwrap();
+    assert_eq!(actual, Message::Rstat(rstat));
+
+    let mut decoder = FrameDecoder::<Message>::with_config(strict);
+    decoder.feed(&frame);
+    match decoder.decode().unwrap() {
+        Decoded::Message(Message::Rstat(_)) => (),
+        other => panic!("expected rstat, got {:?}", other),
+    }
+
+    // Without the extension, the stat would go past its declared size.
+    bump(&mut frame[9..], 0u16.wrapping_sub(10));
+    match from_frame::<Message, _>(&frame) {
+        Err(err) => match err.root() {
+            DeError::SizePrefixTooSmall(_) => (),
+            other => panic!("expected size prefix too small, got {:?}", other),
+        },
+        other => panic!("expected an error, got {:?}", other),
+    }
+}
+
+#[test]
+fn message_serializes_with_type_id() {
+    let rclunk = Rclunk { tag: 1 };
+    let msg = Message::from(Rclunk { tag: 1 });
+
+    let bytes = into_bytes(&msg).unwrap();
+    assert_eq!(bytes, [121, 1, 0]);
+    assert_eq!(from_bytes::<Message, _>(&bytes).unwrap(), msg);
+    assert_eq!(from_slice::<Message>(&bytes).unwrap(), msg);
+
+    // The frame doesn't repeat the type ID.
+    let frame = into_frame(&msg).unwrap();
+    assert_eq!(frame, into_frame(&rclunk).unwrap());
+    let mut streamed = Vec::new();
+    write_message(&msg, &mut streamed).unwrap();
+    assert_eq!(streamed, frame);
+    assert_eq!(&into_vectored(&msg).unwrap().head()[..], &frame[..]);
+
+    assert!(from_bytes::<Message, _>(&[0, 1, 0]).is_err());
+}
+
+#[test]
+fn tflush_names_old_tag() {
+    golden::<Message, _>(Tflush { tag: 1, oldtag: 2 }, &[9, 0, 0, 0, 108, 1, 0, 2, 0]);
+    golden::<Message, _>(Rflush { tag: 1 }, &[7, 0, 0, 0, 109, 1, 0]);
+}
+
+/// Round-trip a message through a frame, knowing only its dialect.
+fn frame_roundtrip<P: Protocol>(msg: &P::Message) -> P::Message {
+    from_frame(into_frame(msg).unwrap()).unwrap()
+}
+
+#[test]
+fn p2000_protocol() {
+    let msg = Message::Tversion(Tversion {
+        tag: NOTAG,
+        msize: 8192,
+        version: P2000::VERSION.into(),
+    });
+    assert_eq!(frame_roundtrip::<P2000>(&msg), msg);
+
+    assert!(P2000::has_type_id(Rerror::MSG_TYPE_ID));
+    assert!(!P2000::has_type_id(106));
+
+    assert_eq!(
+        P2000::response_type_id(Twalk::MSG_TYPE_ID),
+        Some(Rwalk::MSG_TYPE_ID)
+    );
+    assert_eq!(P2000::response_type_id(Rwalk::MSG_TYPE_ID), None);
+    assert_eq!(P2000::response_type_id(106), None);
+
+    assert!(P2000::is_response_to(
+        Twalk::MSG_TYPE_ID,
+        Rwalk::MSG_TYPE_ID
+    ));
+    assert!(P2000::is_response_to(
+        Twalk::MSG_TYPE_ID,
+        Rerror::MSG_TYPE_ID
+    ));
+    assert!(!P2000::is_response_to(
+        Twalk::MSG_TYPE_ID,
+        Ropen::MSG_TYPE_ID
+    ));
+    assert!(!P2000::is_response_to(
+        Rwalk::MSG_TYPE_ID,
+        Rerror::MSG_TYPE_ID
+    ));
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+struct Tsync {
+    tag: u16,
+    fid: u32,
+}
+
+nine::message_type_ids! { Tsync = 150 }
+
+#[test]
+fn custom_messages() {
+    use nine::registry::*;
+
+    let tsync = Tsync { tag: 1, fid: 2 };
+    let frame = into_frame(&Extended::<Message>::custom(Tsync { tag: 1, fid: 2 })).unwrap();
+    assert_eq!(frame, into_frame(&tsync).unwrap());
+
+    // Unknown messages are kept raw, and the reader is left at the next frame.
+    let mut stream = frame.clone();
+    stream.extend(into_frame(&Rclunk { tag: 3 }).unwrap());
+    let mut reader = &stream[..];
+    match read_message::<Extended<Message>, _>(&mut reader).unwrap() {
+        Extended::Unknown(raw) => {
+            assert_eq!(raw.msg_type, 150);
+            assert_eq!(raw.body, [1, 0, 2, 0, 0, 0]);
+            assert_eq!(
+                into_frame(&Extended::<Message>::Unknown(raw)).unwrap(),
+                frame
+            );
+        }
+        other => panic!("expected a raw message, got {:?}", other),
+    }
+    match read_message::<Extended<Message>, _>(&mut reader).unwrap() {
+        Extended::Known(msg) => {
+            assert_eq!(msg, Message::Rclunk(Rclunk { tag: 3 }));
+            assert_eq!(
+                into_frame(&Extended::Known(msg)).unwrap(),
+                into_frame(&Rclunk { tag: 3 }).unwrap()
+            );
+        }
+        other => panic!("expected a known message, got {:?}", other),
+    }
+
+    let mut decoder = FrameDecoder::<Extended<Message>>::new();
+    decoder.feed(&frame);
+    let mut registry = Registry::new();
+    registry.register::<Tsync>();
+    match decoder.decode().unwrap() {
+        Decoded::Message(msg) => match registry.resolve(msg).unwrap() {
+            Extended::Custom(custom) => assert_eq!(custom.downcast_ref(), Some(&tsync)),
+            other => panic!("expected a custom message, got {:?}", other),
+        },
+        other => panic!("expected a message, got {:?}", other),
+    }
+
+    // A custom message that doesn't decode is an error.
+    let short = [6, 0, 0, 0, 150, 1];
+    assert!(registry.from_frame::<Message, _>(&short[..]).is_err());
+
+    // The configuration applies to custom messages as well.
+    let padded = [12, 0, 0, 0, 150, 1, 0, 2, 0, 0, 0, 0xFF];
+    match registry.from_frame::<Message, _>(&padded[..]).unwrap() {
+        Extended::Custom(custom) => assert_eq!(custom.downcast_ref(), Some(&tsync)),
+        other => panic!("expected a custom message, got {:?}", other),
+    }
+    assert!(matches!(
+        registry.from_frame_with::<Message, _>(&padded[..], &strict()),
+        Err(DeError::TrailingBytes(1))
+    ));
+    assert!(matches!(
+        registry.read_message_with::<Message, _>(&padded[..], &strict()),
+        Err(DeError::TrailingBytes(1))
+    ));
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+struct Tprobe {
+    tag: u16,
+    hint: Option<u32>,
+    mark: char,
+    attrs: std::collections::BTreeMap<String, u8>,
+}
+
+nine::message_type_ids! { Tprobe = 152 }
+
+nine::message_set! {
+    #[derive(Debug, PartialEq)]
+    pub enum Probe {
+        Tprobe,
+    }
+}
+
+#[test]
+fn extended_frame_round_trip() {
+    let msg = Probe::Tprobe(Tprobe {
+        tag: 1,
+        hint: Some(2),
+        mark: 'é',
+        attrs: vec![("a".to_string(), 3)].into_iter().collect(),
+    });
+    let expected = [
+        &[20, 0, 0, 0, 152, 1, 0][..],
+        &[1, 2, 0, 0, 0],
+        &[0xC3, 0xA9],
+        &[1, 0, 1, 0, b'a', 3],
+    ]
+    .concat();
+    let encoder = EncoderConfig {
+        extended: true,
+        ..EncoderConfig::default()
+    };
+    let decoder = DecoderConfig {
+        extended: true,
+        ..DecoderConfig::default()
+    };
+
+    assert_eq!(into_frame_with(&msg, &encoder).unwrap(), expected);
+    assert_eq!(serialized_frame_size_with(&msg, &encoder).unwrap(), 20);
+    let mut out = Vec::new();
+    assert_eq!(write_message_with(&msg, &mut out, &encoder).unwrap(), 20);
+    assert_eq!(out, expected);
+
+    assert_eq!(
+        from_frame_with::<Probe, _>(&expected, &decoder).unwrap(),
+        msg
+    );
+    assert_eq!(
+        read_message_with::<Probe, _>(&expected[..], &decoder).unwrap(),
+        msg
+    );
+    let mut frames = FrameDecoder::<Probe>::with_config(decoder.clone());
+    frames.feed(&expected);
+    assert_eq!(frames.decode().unwrap(), Decoded::Message(msg));
+
+    // Without the extended mode, neither side accepts the message.
+    let msg = from_frame_with::<Probe, _>(&expected, &decoder).unwrap();
+    assert!(into_frame(&msg).is_err());
+    assert!(serialized_frame_size(&msg).is_err());
+    assert!(write_message(&msg, &mut Vec::new()).is_err());
+    assert!(from_frame::<Probe, _>(&expected).is_err());
+}
+
+#[test]
+fn raw_bytes_stop_at_size_prefix() {
+    use nine::message::RawBytes;
+
+    let value = SizePrefixed((1u8, RawBytes(vec![2, 3])));
+    let mut bytes = into_bytes(&value).unwrap();
+    assert_eq!(bytes, [3, 0, 1, 2, 3]);
+    assert_eq!(&into_bytes_mut(&value).unwrap()[..], &bytes[..]);
+    assert_eq!(serialized_size(&value).unwrap(), 5);
+    let mut out = Vec::new();
+    into_writer(&value, &mut out).unwrap();
+    assert_eq!(out, bytes);
+    bytes.push(4);
+
+    let mut des = SliceDeserializer::new(&bytes);
+    let actual: SizePrefixed<(u8, RawBytes)> = Deserialize::deserialize(&mut des).unwrap();
+    assert_eq!(actual, value);
+    assert_eq!(des.input, [4]);
+    let mut des = ReadDeserializer::new(&bytes[..]);
+    let actual: SizePrefixed<(u8, RawBytes)> = Deserialize::deserialize(&mut des).unwrap();
+    assert_eq!(actual, value);
+    assert_eq!(des.0, [4]);
+    let mut des = BufDeserializer::new(&bytes[..]);
+    let actual: SizePrefixed<(u8, RawBytes)> = Deserialize::deserialize(&mut des).unwrap();
+    assert_eq!(actual, value);
+    assert_eq!(des.buf, [4]);
+}
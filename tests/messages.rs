@@ -2,6 +2,10 @@
 
 extern crate byteorder;
 extern crate nine;
+
+mod common;
+
+use crate::common::stat;
 use byteorder::{WriteBytesExt, LE};
 use nine::de::*;
 use nine::p2000::*;
@@ -47,6 +51,11 @@ fn write_str<W: Write + WriteBytesExt>(w: &mut W, s: &str) {
 fn write_stat<W: Write + WriteBytesExt>(bytes: &mut W, s: &Stat) {
     bytes.write_u16::<LE>(stat_len(s) + 2).unwrap();
     bytes.write_u16::<LE>(stat_len(s)).unwrap();
+    write_stat_body(bytes, s);
+}
+
+/// NOT including any size fields.
+fn write_stat_body<W: Write + WriteBytesExt>(bytes: &mut W, s: &Stat) {
     bytes.write_u16::<LE>(s.type_).unwrap();
     bytes.write_u32::<LE>(s.dev).unwrap();
     write_qid(bytes, &s.qid);
@@ -80,7 +89,7 @@ fn version() {
         version: "9p2000".into(),
     };
 
-    assert_eq!(size_for(&expected_msg).unwrap(), 2 + 4 + 8);
+    assert_eq!(serialized_size(&expected_msg).unwrap(), 2 + 4 + 8);
 
     assert_eq!(actual_msg, expected_msg);
 
@@ -117,7 +126,7 @@ fn rauth() {
 
     let actual_msg: Rauth = Deserialize::deserialize(&mut des).unwrap();
 
-    assert_eq!(size_for(&expected_msg).unwrap(), 2 + 13);
+    assert_eq!(serialized_size(&expected_msg).unwrap(), 2 + 13);
 
     assert_eq!(actual_msg, expected_msg);
 
@@ -134,23 +143,7 @@ fn rauth() {
 fn rstat() {
     let mut bytes = Cursor::new(Vec::<u8>::new());
     let tag = 1;
-    let stat = Stat {
-        type_: 1,
-        dev: 2,
-        qid: Qid {
-            file_type: FileType::FILE,
-            version: 3,
-            path: 4,
-        },
-        mode: FileMode::OWNER_READ | FileMode::OWNER_WRITE,
-        atime: 5,
-        mtime: 6,
-        length: 512,
-        name: "hello".into(),
-        uid: "glenda".into(),
-        gid: "glenda".into(),
-        muid: "glenda".into(),
-    };
+    let stat = stat();
 
     bytes.write_u16::<LE>(tag).unwrap();
     write_stat(&mut bytes, &stat);
@@ -165,7 +158,7 @@ fn rstat() {
     let actual_msg: Rstat = Deserialize::deserialize(&mut des).unwrap();
 
     assert_eq!(
-        size_for(&expected_msg).unwrap(),
+        serialized_size(&expected_msg).unwrap(),
         2 + 4 + stat_len(&expected_msg.stat) as u32
     );
 
@@ -202,7 +195,10 @@ fn twalk() {
 
     let actual_msg: Twalk = Deserialize::deserialize(&mut des).unwrap();
 
-    assert_eq!(size_for(&expected_msg).unwrap(), 2 + 4 + 4 + 2 + 4 + 6);
+    assert_eq!(
+        serialized_size(&expected_msg).unwrap(),
+        2 + 4 + 4 + 2 + 4 + 6
+    );
 
     assert_eq!(actual_msg, expected_msg);
 
@@ -232,7 +228,7 @@ fn rread() {
 
     let actual_msg = Rread::deserialize(&mut des).unwrap();
 
-    assert_eq!(size_for(&expected_msg).unwrap(), 2 + 4 + 5);
+    assert_eq!(serialized_size(&expected_msg).unwrap(), 2 + 4 + 5);
 
     assert_eq!(actual_msg, expected_msg);
 
@@ -242,3 +238,322 @@ fn rread() {
 
     assert_eq!(expected_ser_buf, serializer.writer.into_inner());
 }
+
+#[test]
+fn borrowed_twrite() {
+    #[derive(serde::Deserialize, Debug, PartialEq)]
+    struct BorrowedTwrite<'a> {
+        tag: u16,
+        fid: u32,
+        offset: u64,
+        data: &'a [u8],
+    }
+
+    let msg = Twrite {
+        tag: 1,
+        fid: 2,
+        offset: 3,
+        data: b"hello".to_vec(),
+    };
+    let bytes = into_bytes(&msg).unwrap();
+
+    let borrowed: BorrowedTwrite = from_slice(&bytes).unwrap();
+
+    assert_eq!(borrowed.data, &bytes[bytes.len() - 5..]);
+    assert_eq!(borrowed.data.as_ptr(), bytes[bytes.len() - 5..].as_ptr());
+
+    let owned: Twrite = from_slice(&bytes).unwrap();
+    assert_eq!(owned, msg);
+}
+
+#[test]
+fn bytes_mut_matches_write_serializer() {
+    let rstat = Rstat {
+        tag: 1,
+        stat: stat(),
+    };
+    let twalk = Twalk {
+        tag: 1,
+        fid: 2,
+        newfid: 3,
+        wname: vec!["one".into(), "two".into()],
+    };
+
+    assert_eq!(into_bytes_mut(&rstat).unwrap(), into_bytes(&rstat).unwrap());
+    assert_eq!(into_bytes_mut(&twalk).unwrap(), into_bytes(&twalk).unwrap());
+    assert_eq!(
+        into_bytes_mut(&rstat.stat).unwrap(),
+        into_bytes(&rstat.stat).unwrap()
+    );
+}
+
+#[test]
+fn stream_matches_write_serializer() {
+    let twstat = Twstat {
+        tag: 1,
+        fid: 2,
+        stat: stat(),
+    };
+    let twalk = Twalk {
+        tag: 1,
+        fid: 2,
+        newfid: 3,
+        wname: vec!["one".into(), "two".into()],
+    };
+
+    // Vec<u8> can't seek, so everything has to be written in order.
+    let mut out = Vec::new();
+    assert_eq!(
+        into_writer(&twstat, &mut out).unwrap(),
+        serialized_size(&twstat).unwrap()
+    );
+    assert_eq!(out, into_bytes(&twstat).unwrap());
+
+    let mut out = Vec::new();
+    into_writer(&twalk, &mut out).unwrap();
+    assert_eq!(out, into_bytes(&twalk).unwrap());
+
+    let mut out = Vec::new();
+    into_writer(&twstat.stat, &mut out).unwrap();
+    assert_eq!(out, into_bytes(&twstat.stat).unwrap());
+}
+
+#[test]
+fn shared_rread() {
+    #[derive(serde::Deserialize, Debug, PartialEq)]
+    struct SharedRread {
+        tag: u16,
+        #[serde(deserialize_with = "deserialize_shared_bytes")]
+        data: bytes::Bytes,
+    }
+
+    let msg = Rread {
+        tag: 1,
+        data: b"hello".to_vec(),
+    };
+    let buf = bytes::Bytes::from(into_bytes(&msg).unwrap());
+
+    let shared: SharedRread = from_buf(buf.clone()).unwrap();
+    assert_eq!(shared.data, buf.slice(6..));
+    assert_eq!(shared.data.as_ptr(), buf[6..].as_ptr());
+
+    let copied: SharedRread = from_bytes(&buf).unwrap();
+    assert_eq!(copied, shared);
+
+    let owned: Rread = from_buf(buf).unwrap();
+    assert_eq!(owned, msg);
+}
+
+#[test]
+fn shared_bytes_are_not_reused() {
+    #[derive(serde::Serialize)]
+    struct Fields {
+        #[serde(serialize_with = "serialize_bytes")]
+        first: Vec<u8>,
+        #[serde(serialize_with = "serialize_bytes")]
+        second: Vec<u8>,
+        #[serde(serialize_with = "serialize_bytes")]
+        third: Vec<u8>,
+    }
+    #[derive(serde::Deserialize, Debug, PartialEq)]
+    struct Mixed {
+        #[serde(deserialize_with = "deserialize_shared_bytes")]
+        first: bytes::Bytes,
+        #[serde(deserialize_with = "deserialize_owned_bytes")]
+        second: Vec<u8>,
+        #[serde(deserialize_with = "deserialize_shared_bytes")]
+        third: bytes::Bytes,
+    }
+
+    let fields = Fields {
+        first: b"one".to_vec(),
+        second: b"two".to_vec(),
+        third: b"three".to_vec(),
+    };
+    let buf = bytes::Bytes::from(into_bytes(&fields).unwrap());
+
+    let mixed: Mixed = from_buf(buf.clone()).unwrap();
+    assert_eq!(mixed.first, &b"one"[..]);
+    assert_eq!(mixed.first.as_ptr(), buf[4..].as_ptr());
+    assert_eq!(mixed.second, b"two");
+    assert_eq!(mixed.third, &b"three"[..]);
+    assert_eq!(mixed.third.as_ptr(), buf[18..].as_ptr());
+
+    // Nothing is left behind for deserializers without a shared buffer.
+    let copied: Mixed = from_slice(&buf).unwrap();
+    assert_eq!(copied, mixed);
+    assert_ne!(copied.first.as_ptr(), mixed.first.as_ptr());
+    let copied: Mixed = from_bytes(&buf).unwrap();
+    assert_eq!(copied, mixed);
+}
+
+#[test]
+fn hostile_byte_len() {
+    let mut bytes = Vec::new();
+    bytes.write_u16::<LE>(1).unwrap();
+    bytes.write_u32::<LE>(u32::max_value()).unwrap();
+    bytes.write_all(b"abc").unwrap();
+
+    let err = Rread::deserialize(&mut des(&bytes[..])).unwrap_err();
+    assert!(err.is_eof());
+
+    let limits = Limits {
+        bytes_len: 8192,
+        ..Limits::default()
+    };
+    let mut des = ReadDeserializer::with_limits(&bytes[..], limits);
+    match Rread::deserialize(&mut des).as_ref().map_err(DeError::root) {
+        Err(DeError::LimitExceeded {
+            kind: "bytes",
+            limit: 8192,
+            ..
+        }) => (),
+        other => panic!("expected limit exceeded, got {:?}", other),
+    }
+}
+
+#[test]
+fn string_seq_and_total_limits() {
+    let twalk = Twalk {
+        tag: 1,
+        fid: 2,
+        newfid: 3,
+        wname: vec!["one".into(), "two".into()],
+    };
+    let bytes = into_bytes(&twalk).unwrap();
+
+    let limited = |limits: Limits| {
+        let mut des = ReadDeserializer::with_limits(&bytes[..], limits);
+        match Twalk::deserialize(&mut des).as_ref().map_err(DeError::root) {
+            Err(DeError::LimitExceeded { kind, .. }) => *kind,
+            other => panic!("expected limit exceeded, got {:?}", other),
+        }
+    };
+
+    assert_eq!(
+        limited(Limits {
+            str_len: 2,
+            ..Limits::default()
+        }),
+        "string"
+    );
+    assert_eq!(
+        limited(Limits {
+            seq_len: 1,
+            ..Limits::default()
+        }),
+        "sequence"
+    );
+    assert_eq!(
+        limited(Limits {
+            total: bytes.len() as u64 - 1,
+            ..Limits::default()
+        }),
+        "total"
+    );
+
+    let limits = Limits {
+        str_len: 3,
+        seq_len: 2,
+        total: bytes.len() as u64,
+        ..Limits::default()
+    };
+    let mut des = ReadDeserializer::with_limits(&bytes[..], limits);
+    assert_eq!(Twalk::deserialize(&mut des).unwrap(), twalk);
+}
+
+#[test]
+fn dir_entries() {
+    let named = |name: &str| Stat {
+        name: name.into(),
+        ..stat()
+    };
+    let stats = vec![named("a"), named("bb"), named("ccc")];
+    let first_two = (stat_len(&stats[0]) + 2 + stat_len(&stats[1]) + 2) as u32;
+
+    let mut data = Vec::new();
+    let packed = pack_dir_entries(&stats, first_two + 1, &mut data).unwrap();
+    assert_eq!(packed.entries, 2);
+    assert_eq!(packed.len, first_two);
+    assert_eq!(data.len() as u32, first_two);
+
+    let mut expected = Cursor::new(Vec::new());
+    for stat in &stats[..2] {
+        expected.write_u16::<LE>(stat_len(stat)).unwrap();
+        write_stat_body(&mut expected, stat);
+    }
+    assert_eq!(data, expected.into_inner());
+
+    let rest = pack_dir_entries(&stats[packed.entries..], 8192, &mut data).unwrap();
+    assert_eq!(rest.entries, 1);
+    let parsed: Vec<Stat> = DirEntries::new(&data).collect::<Result<_, _>>().unwrap();
+    assert_eq!(parsed, stats);
+
+    let none_fit = pack_dir_entries(&stats, 10, &mut Vec::new()).unwrap();
+    assert_eq!(none_fit.entries, 0);
+
+    let mut entries = DirEntries::new(&data[..data.len() - 1]);
+    assert_eq!(entries.next().unwrap().unwrap(), stats[0]);
+    assert_eq!(entries.next().unwrap().unwrap(), stats[1]);
+    assert!(entries.next().unwrap().unwrap_err().is_eof());
+    assert!(entries.next().is_none());
+}
+
+#[test]
+fn size_prefixed() {
+    // Not the 9p stat, so it shouldn't be treated like one.
+    #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+    struct Stat {
+        a: u16,
+        b: String,
+    }
+    let plain = Stat {
+        a: 1,
+        b: "hi".into(),
+    };
+    assert_eq!(into_bytes(&plain).unwrap(), [1, 0, 2, 0, b'h', b'i']);
+
+    let single = SizePrefixed(plain);
+    let bytes = into_bytes(&single).unwrap();
+    assert_eq!(bytes, [6, 0, 1, 0, 2, 0, b'h', b'i']);
+    assert_eq!(into_bytes_mut(&single).unwrap(), bytes);
+    assert_eq!(serialized_size(&single).unwrap(), 8);
+    let actual: SizePrefixed<Stat> = from_slice(&bytes).unwrap();
+    assert_eq!(actual, single);
+
+    let double = SizePrefixed(single);
+    let bytes = into_bytes(&double).unwrap();
+    assert_eq!(&bytes[..4], [8, 0, 6, 0]);
+    let mut streamed = Vec::new();
+    into_writer(&double, &mut streamed).unwrap();
+    assert_eq!(streamed, bytes);
+    let actual: SizePrefixed<SizePrefixed<Stat>> = from_bytes(&bytes).unwrap();
+    assert_eq!(actual, double);
+
+    // Bytes a value doesn't know about are skipped.
+    let mut longer = bytes.clone();
+    longer[0] = 9;
+    longer.push(0xEE);
+    longer.push(0xFF);
+    let mut des = SliceDeserializer::new(&longer);
+    let actual: SizePrefixed<SizePrefixed<Stat>> = Deserialize::deserialize(&mut des).unwrap();
+    assert_eq!(actual, double);
+    assert_eq!(des.input, [0xFF]);
+    let actual: SizePrefixed<SizePrefixed<Stat>> = from_bytes(&longer).unwrap();
+    assert_eq!(actual, double);
+    let actual: SizePrefixed<SizePrefixed<Stat>> = from_buf(&longer[..]).unwrap();
+    assert_eq!(actual, double);
+
+    // Reading past the declared size fails, whichever prefix is too small.
+    for &(index, declared) in &[(0, 7), (2, 5)] {
+        let mut shorter = bytes.clone();
+        shorter[index] = declared;
+        let check = |err: DeError| match err.root() {
+            DeError::SizePrefixTooSmall(d) if *d == declared as u16 => (),
+            other => panic!("expected size prefix too small, got {:?}", other),
+        };
+        check(from_slice::<SizePrefixed<SizePrefixed<Stat>>>(&shorter).unwrap_err());
+        check(from_bytes::<SizePrefixed<SizePrefixed<Stat>>, _>(&shorter).unwrap_err());
+        check(from_buf::<SizePrefixed<SizePrefixed<Stat>>, _>(&shorter[..]).unwrap_err());
+    }
+}
@@ -0,0 +1,647 @@
+//! Byte-exact tests of the 9P2000.L messages.
+
+extern crate nine;
+
+mod common;
+
+use crate::common::golden;
+use nine::de::*;
+use nine::message::{ConstMessageTypeId, Protocol};
+use nine::p2000::l::*;
+use nine::p2000::{FileType, NOTAG};
+use nine::ser::*;
+use std::io::{self, ErrorKind};
+
+const QID: [u8; 13] = [0x80, 1, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0];
+
+fn qid() -> Qid {
+    Qid {
+        file_type: FileType::DIR,
+        version: 1,
+        path: 2,
+    }
+}
+
+fn rw_r_r() -> Mode {
+    Mode::OWNER_READ | Mode::OWNER_WRITE | Mode::GROUP_READ | Mode::OTHER_READ
+}
+
+#[test]
+fn statfs() {
+    golden::<Message, _>(
+        Tstatfs { tag: 1, fid: 2 },
+        &[11, 0, 0, 0, 8, 1, 0, 2, 0, 0, 0],
+    );
+    golden::<Message, _>(
+        Rstatfs {
+            tag: 1,
+            r#type: 0x0102_1997,
+            bsize: 4096,
+            blocks: 3,
+            bfree: 4,
+            bavail: 5,
+            files: 6,
+            ffree: 7,
+            fsid: 8,
+            namelen: 255,
+        },
+        &[
+            67, 0, 0, 0, 9, 1, 0, //
+            0x97, 0x19, 0x02, 0x01, //
+            0, 0x10, 0, 0, //
+            3, 0, 0, 0, 0, 0, 0, 0, //
+            4, 0, 0, 0, 0, 0, 0, 0, //
+            5, 0, 0, 0, 0, 0, 0, 0, //
+            6, 0, 0, 0, 0, 0, 0, 0, //
+            7, 0, 0, 0, 0, 0, 0, 0, //
+            8, 0, 0, 0, 0, 0, 0, 0, //
+            255, 0, 0, 0,
+        ],
+    );
+}
+
+#[test]
+fn lopen_and_lcreate() {
+    golden::<Message, _>(
+        Tlopen {
+            tag: 1,
+            fid: 2,
+            flags: OpenFlags::from(Access::ReadWrite),
+        },
+        &[15, 0, 0, 0, 12, 1, 0, 2, 0, 0, 0, 2, 0, 0, 0],
+    );
+    golden::<Message, _>(
+        Tlcreate {
+            tag: 1,
+            fid: 2,
+            name: "a".to_owned(),
+            flags: OpenFlags::from(Access::ReadWrite),
+            mode: rw_r_r(),
+            gid: 3,
+        },
+        &[
+            26, 0, 0, 0, 14, 1, 0, 2, 0, 0, 0, 1, 0, b'a', 2, 0, 0, 0, 0xA4, 1, 0, 0, 3, 0, 0, 0,
+        ],
+    );
+    golden::<Message, _>(
+        Rlcreate {
+            tag: 1,
+            qid: qid(),
+            iounit: 4,
+        },
+        &[&[24, 0, 0, 0, 15, 1, 0][..], &QID, &[4, 0, 0, 0]].concat(),
+    );
+}
+
+#[test]
+fn symlink_mknod_and_mkdir() {
+    golden::<Message, _>(
+        Tsymlink {
+            tag: 1,
+            fid: 2,
+            name: "a".to_owned(),
+            symtgt: "b".to_owned(),
+            gid: 3,
+        },
+        &[
+            21, 0, 0, 0, 16, 1, 0, 2, 0, 0, 0, 1, 0, b'a', 1, 0, b'b', 3, 0, 0, 0,
+        ],
+    );
+    golden::<Message, _>(
+        Rsymlink { tag: 1, qid: qid() },
+        &[&[20, 0, 0, 0, 17, 1, 0][..], &QID].concat(),
+    );
+    golden::<Message, _>(
+        Tmknod {
+            tag: 1,
+            dfid: 2,
+            name: "a".to_owned(),
+            mode: Mode::from(FileKind::Char) | rw_r_r(),
+            major: 4,
+            minor: 5,
+            gid: 3,
+        },
+        &[
+            30, 0, 0, 0, 18, 1, 0, 2, 0, 0, 0, 1, 0, b'a', 0xA4, 0x21, 0, 0, 4, 0, 0, 0, 5, 0, 0,
+            0, 3, 0, 0, 0,
+        ],
+    );
+    golden::<Message, _>(
+        Rmknod { tag: 1, qid: qid() },
+        &[&[20, 0, 0, 0, 19, 1, 0][..], &QID].concat(),
+    );
+    golden::<Message, _>(
+        Tmkdir {
+            tag: 1,
+            dfid: 2,
+            name: "a".to_owned(),
+            mode: Mode::from_bits(0o755).unwrap(),
+            gid: 3,
+        },
+        &[
+            22, 0, 0, 0, 72, 1, 0, 2, 0, 0, 0, 1, 0, b'a', 0xED, 1, 0, 0, 3, 0, 0, 0,
+        ],
+    );
+    golden::<Message, _>(
+        Rmkdir { tag: 1, qid: qid() },
+        &[&[20, 0, 0, 0, 73, 1, 0][..], &QID].concat(),
+    );
+}
+
+#[test]
+fn links_and_renames() {
+    golden::<Message, _>(
+        Trename {
+            tag: 1,
+            fid: 2,
+            dfid: 3,
+            name: "a".to_owned(),
+        },
+        &[18, 0, 0, 0, 20, 1, 0, 2, 0, 0, 0, 3, 0, 0, 0, 1, 0, b'a'],
+    );
+    golden::<Message, _>(
+        Treadlink { tag: 1, fid: 2 },
+        &[11, 0, 0, 0, 22, 1, 0, 2, 0, 0, 0],
+    );
+    golden::<Message, _>(
+        Rreadlink {
+            tag: 1,
+            target: "a".to_owned(),
+        },
+        &[10, 0, 0, 0, 23, 1, 0, 1, 0, b'a'],
+    );
+    golden::<Message, _>(
+        Tlink {
+            tag: 1,
+            dfid: 2,
+            fid: 3,
+            name: "a".to_owned(),
+        },
+        &[18, 0, 0, 0, 70, 1, 0, 2, 0, 0, 0, 3, 0, 0, 0, 1, 0, b'a'],
+    );
+    golden::<Message, _>(Rlink { tag: 1 }, &[7, 0, 0, 0, 71, 1, 0]);
+    golden::<Message, _>(
+        Trenameat {
+            tag: 1,
+            olddirfid: 2,
+            oldname: "a".to_owned(),
+            newdirfid: 3,
+            newname: "b".to_owned(),
+        },
+        &[
+            21, 0, 0, 0, 74, 1, 0, 2, 0, 0, 0, 1, 0, b'a', 3, 0, 0, 0, 1, 0, b'b',
+        ],
+    );
+    golden::<Message, _>(Rrenameat { tag: 1 }, &[7, 0, 0, 0, 75, 1, 0]);
+    golden::<Message, _>(
+        Tunlinkat {
+            tag: 1,
+            dirfd: 2,
+            name: "a".to_owned(),
+            flags: 0x200,
+        },
+        &[18, 0, 0, 0, 76, 1, 0, 2, 0, 0, 0, 1, 0, b'a', 0, 2, 0, 0],
+    );
+    golden::<Message, _>(Runlinkat { tag: 1 }, &[7, 0, 0, 0, 77, 1, 0]);
+}
+
+#[test]
+fn getattr_and_setattr() {
+    golden::<Message, _>(
+        Tgetattr {
+            tag: 1,
+            fid: 2,
+            request_mask: GetattrMask::ALL,
+        },
+        &[
+            19, 0, 0, 0, 24, 1, 0, 2, 0, 0, 0, 0xFF, 0x3F, 0, 0, 0, 0, 0, 0,
+        ],
+    );
+    golden::<Message, _>(
+        Rgetattr {
+            tag: 1,
+            valid: GetattrMask::BASIC,
+            qid: qid(),
+            mode: Mode::from(FileKind::Regular) | rw_r_r(),
+            uid: 1000,
+            gid: 1000,
+            nlink: 1,
+            rdev: 0,
+            size: 5,
+            blksize: 4096,
+            blocks: 8,
+            atime_sec: 10,
+            atime_nsec: 11,
+            mtime_sec: 12,
+            mtime_nsec: 13,
+            ctime_sec: 14,
+            ctime_nsec: 15,
+            btime_sec: 16,
+            btime_nsec: 17,
+            gen: 18,
+            data_version: 19,
+        },
+        &[
+            &[160, 0, 0, 0, 25, 1, 0][..],
+            &[0xFF, 0x07, 0, 0, 0, 0, 0, 0],
+            &QID,
+            &[0xA4, 0x81, 0, 0],
+            &[0xE8, 3, 0, 0],
+            &[0xE8, 3, 0, 0],
+            &[1, 0, 0, 0, 0, 0, 0, 0],
+            &[0, 0, 0, 0, 0, 0, 0, 0],
+            &[5, 0, 0, 0, 0, 0, 0, 0],
+            &[0, 0x10, 0, 0, 0, 0, 0, 0],
+            &[8, 0, 0, 0, 0, 0, 0, 0],
+            &[10, 0, 0, 0, 0, 0, 0, 0],
+            &[11, 0, 0, 0, 0, 0, 0, 0],
+            &[12, 0, 0, 0, 0, 0, 0, 0],
+            &[13, 0, 0, 0, 0, 0, 0, 0],
+            &[14, 0, 0, 0, 0, 0, 0, 0],
+            &[15, 0, 0, 0, 0, 0, 0, 0],
+            &[16, 0, 0, 0, 0, 0, 0, 0],
+            &[17, 0, 0, 0, 0, 0, 0, 0],
+            &[18, 0, 0, 0, 0, 0, 0, 0],
+            &[19, 0, 0, 0, 0, 0, 0, 0],
+        ]
+        .concat(),
+    );
+    golden::<Message, _>(Rsetattr { tag: 1 }, &[7, 0, 0, 0, 27, 1, 0]);
+}
+
+#[test]
+fn xattrs() {
+    golden::<Message, _>(
+        Txattrwalk {
+            tag: 1,
+            fid: 2,
+            newfid: 3,
+            name: "a".to_owned(),
+        },
+        &[18, 0, 0, 0, 30, 1, 0, 2, 0, 0, 0, 3, 0, 0, 0, 1, 0, b'a'],
+    );
+    golden::<Message, _>(
+        Rxattrwalk { tag: 1, size: 4 },
+        &[15, 0, 0, 0, 31, 1, 0, 4, 0, 0, 0, 0, 0, 0, 0],
+    );
+    golden::<Message, _>(
+        Txattrcreate {
+            tag: 1,
+            fid: 2,
+            name: "a".to_owned(),
+            attr_size: 4,
+            flags: 1,
+        },
+        &[
+            26, 0, 0, 0, 32, 1, 0, 2, 0, 0, 0, 1, 0, b'a', 4, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0,
+        ],
+    );
+    golden::<Message, _>(Rxattrcreate { tag: 1 }, &[7, 0, 0, 0, 33, 1, 0]);
+}
+
+#[test]
+fn readdir() {
+    golden::<Message, _>(
+        Treaddir {
+            tag: 1,
+            fid: 2,
+            offset: 3,
+            count: 4,
+        },
+        &[
+            23, 0, 0, 0, 40, 1, 0, 2, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 4, 0, 0, 0,
+        ],
+    );
+
+    let dirent = Dirent {
+        qid: qid(),
+        offset: 5,
+        type_: 4,
+        name: "a".to_owned(),
+    };
+    let data = [&QID[..], &[5, 0, 0, 0, 0, 0, 0, 0, 4, 1, 0, b'a']].concat();
+    assert_eq!(into_bytes(&dirent).unwrap(), data);
+    assert_eq!(from_bytes::<Dirent, _>(&data).unwrap(), dirent);
+
+    golden::<Message, _>(
+        Rreaddir {
+            tag: 1,
+            data: data.clone(),
+        },
+        &[&[36, 0, 0, 0, 41, 1, 0, 25, 0, 0, 0][..], &data].concat(),
+    );
+}
+
+#[test]
+fn fsync_and_locks() {
+    golden::<Message, _>(
+        Tfsync {
+            tag: 1,
+            fid: 2,
+            datasync: 1,
+        },
+        &[15, 0, 0, 0, 50, 1, 0, 2, 0, 0, 0, 1, 0, 0, 0],
+    );
+    golden::<Message, _>(Rfsync { tag: 1 }, &[7, 0, 0, 0, 51, 1, 0]);
+    golden::<Message, _>(
+        Tlock {
+            tag: 1,
+            fid: 2,
+            type_: 1,
+            flags: 1,
+            start: 0,
+            length: 0,
+            proc_id: 3,
+            client_id: "a".to_owned(),
+        },
+        &[
+            39, 0, 0, 0, 52, 1, 0, 2, 0, 0, 0, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 3, 0, 0, 0, 1, 0, b'a',
+        ],
+    );
+    golden::<Message, _>(Rlock { tag: 1, status: 0 }, &[8, 0, 0, 0, 53, 1, 0, 0]);
+    golden::<Message, _>(
+        Tgetlock {
+            tag: 1,
+            fid: 2,
+            type_: 0,
+            start: 4,
+            length: 5,
+            proc_id: 3,
+            client_id: "a".to_owned(),
+        },
+        &[
+            35, 0, 0, 0, 54, 1, 0, 2, 0, 0, 0, 0, 4, 0, 0, 0, 0, 0, 0, 0, 5, 0, 0, 0, 0, 0, 0, 0,
+            3, 0, 0, 0, 1, 0, b'a',
+        ],
+    );
+    golden::<Message, _>(
+        Rgetlock {
+            tag: 1,
+            type_: 2,
+            start: 4,
+            length: 5,
+            proc_id: 3,
+            client_id: "a".to_owned(),
+        },
+        &[
+            31, 0, 0, 0, 55, 1, 0, 2, 4, 0, 0, 0, 0, 0, 0, 0, 5, 0, 0, 0, 0, 0, 0, 0, 3, 0, 0, 0,
+            1, 0, b'a',
+        ],
+    );
+}
+
+#[test]
+fn version_auth_and_attach() {
+    golden::<Message, _>(
+        Tversion {
+            tag: NOTAG,
+            msize: 8192,
+            version: P2000L::VERSION.to_owned(),
+        },
+        &[
+            21, 0, 0, 0, 100, 0xFF, 0xFF, 0, 0x20, 0, 0, 8, 0, b'9', b'P', b'2', b'0', b'0', b'0',
+            b'.', b'L',
+        ],
+    );
+    golden::<Message, _>(
+        Tauth {
+            tag: 1,
+            afid: 2,
+            uname: "a".to_owned(),
+            aname: "".to_owned(),
+            n_uname: 1000,
+        },
+        &[
+            20, 0, 0, 0, 102, 1, 0, 2, 0, 0, 0, 1, 0, b'a', 0, 0, 0xE8, 3, 0, 0,
+        ],
+    );
+    golden::<Message, _>(
+        Rauth {
+            tag: 1,
+            aqid: qid(),
+        },
+        &[&[20, 0, 0, 0, 103, 1, 0][..], &QID].concat(),
+    );
+    golden::<Message, _>(
+        Tattach {
+            tag: 1,
+            fid: 2,
+            afid: !0,
+            uname: "a".to_owned(),
+            aname: "".to_owned(),
+            n_uname: 1000,
+        },
+        &[
+            24, 0, 0, 0, 104, 1, 0, 2, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF, 1, 0, b'a', 0, 0, 0xE8, 3,
+            0, 0,
+        ],
+    );
+    golden::<Message, _>(
+        Rattach { tag: 1, qid: qid() },
+        &[&[20, 0, 0, 0, 105, 1, 0][..], &QID].concat(),
+    );
+}
+
+#[test]
+fn shared_file_messages() {
+    golden::<Message, _>(
+        Twalk {
+            tag: 1,
+            fid: 2,
+            newfid: 3,
+            wname: vec!["a".to_owned()],
+        },
+        &[
+            20, 0, 0, 0, 110, 1, 0, 2, 0, 0, 0, 3, 0, 0, 0, 1, 0, 1, 0, b'a',
+        ],
+    );
+    golden::<Message, _>(
+        Rwalk {
+            tag: 1,
+            wqid: vec![qid()],
+        },
+        &[&[22, 0, 0, 0, 111, 1, 0, 1, 0][..], &QID].concat(),
+    );
+    golden::<Message, _>(
+        Tread {
+            tag: 1,
+            fid: 2,
+            offset: 3,
+            count: 4,
+        },
+        &[
+            23, 0, 0, 0, 116, 1, 0, 2, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 4, 0, 0, 0,
+        ],
+    );
+    golden::<Message, _>(
+        Rread {
+            tag: 1,
+            data: vec![1, 2],
+        },
+        &[13, 0, 0, 0, 117, 1, 0, 2, 0, 0, 0, 1, 2],
+    );
+    golden::<Message, _>(
+        Twrite {
+            tag: 1,
+            fid: 2,
+            offset: 3,
+            data: vec![1, 2],
+        },
+        &[
+            25, 0, 0, 0, 118, 1, 0, 2, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 1, 2,
+        ],
+    );
+    golden::<Message, _>(
+        Rwrite { tag: 1, count: 2 },
+        &[11, 0, 0, 0, 119, 1, 0, 2, 0, 0, 0],
+    );
+    golden::<Message, _>(
+        Tclunk { tag: 1, fid: 2 },
+        &[11, 0, 0, 0, 120, 1, 0, 2, 0, 0, 0],
+    );
+    golden::<Message, _>(Rclunk { tag: 1 }, &[7, 0, 0, 0, 121, 1, 0]);
+    golden::<Message, _>(
+        Tremove { tag: 1, fid: 2 },
+        &[11, 0, 0, 0, 122, 1, 0, 2, 0, 0, 0],
+    );
+    golden::<Message, _>(Rremove { tag: 1 }, &[7, 0, 0, 0, 123, 1, 0]);
+}
+
+#[test]
+fn p2000l_protocol() {
+    assert_eq!(P2000L::response_type_id(Tgetattr::MSG_TYPE_ID), Some(25));
+    assert!(P2000L::is_response_to(
+        Tlopen::MSG_TYPE_ID,
+        Rlerror::MSG_TYPE_ID
+    ));
+    // 9P2000's Topen and Rerror aren't part of the .L dialect.
+    assert!(!P2000L::has_type_id(112));
+    assert!(!P2000L::has_type_id(107));
+}
+
+#[test]
+fn linux_layouts() {
+    golden::<Message, _>(
+        Rlerror {
+            tag: 1,
+            ecode: Errno::ENOENT,
+        },
+        &[11, 0, 0, 0, 7, 1, 0, 2, 0, 0, 0],
+    );
+    golden::<Message, _>(
+        Rlopen {
+            tag: 1,
+            qid: qid(),
+            iounit: 0,
+        },
+        &[&[24, 0, 0, 0, 13, 1, 0][..], &QID, &[0, 0, 0, 0]].concat(),
+    );
+    golden::<Message, _>(Rrename { tag: 1 }, &[7, 0, 0, 0, 21, 1, 0]);
+    // A chmod and truncate
+    golden::<Message, _>(
+        Tsetattr {
+            tag: 1,
+            fid: 2,
+            valid: SetattrMask::MODE | SetattrMask::SIZE,
+            mode: rw_r_r(),
+            uid: 0,
+            gid: 0,
+            size: 5,
+            atime_sec: 0,
+            atime_nsec: 0,
+            mtime_sec: 0,
+            mtime_nsec: 0,
+        },
+        &[
+            &[67, 0, 0, 0, 26, 1, 0][..],
+            &[2, 0, 0, 0],
+            &[9, 0, 0, 0],
+            &[0xA4, 1, 0, 0],
+            &[0, 0, 0, 0],
+            &[0, 0, 0, 0],
+            &[5, 0, 0, 0, 0, 0, 0, 0],
+            &[0; 32],
+        ]
+        .concat(),
+    );
+    golden::<Message, _>(Tflush { tag: 1, oldtag: 2 }, &[9, 0, 0, 0, 108, 1, 0, 2, 0]);
+    golden::<Message, _>(Rflush { tag: 1 }, &[7, 0, 0, 0, 109, 1, 0]);
+}
+
+#[test]
+fn errno() {
+    assert_eq!(Errno::from(2), Errno::ENOENT);
+    assert_eq!(Errno::from(1000), Errno::Other(1000));
+    assert_eq!(u32::from(Errno::EDQUOT), 122);
+    assert_eq!(u32::from(Errno::Other(1000)), 1000);
+
+    assert_eq!(Errno::ENOENT.name(), Some("ENOENT"));
+    assert_eq!(Errno::ENOENT.to_string(), "No such file or directory");
+    assert_eq!(Errno::Other(1000).name(), None);
+    assert_eq!(Errno::Other(1000).to_string(), "Unknown error 1000");
+
+    let err = std::fs::File::open("/nonexistent/nine").unwrap_err();
+    assert_eq!(Errno::from(err), Errno::ENOENT);
+    let err = io::Error::from(Errno::EACCES);
+    assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+    assert_eq!(Errno::from(&err), Errno::EACCES);
+    assert_eq!(Errno::from(ErrorKind::AlreadyExists), Errno::EEXIST);
+    assert_eq!(ErrorKind::from(Errno::ENOSPC), ErrorKind::Other);
+    assert_eq!(
+        Errno::from(io::Error::new(ErrorKind::Other, "oops")),
+        Errno::EIO
+    );
+}
+
+#[test]
+fn flags() {
+    assert_eq!(
+        OpenFlags::from_bits(0o1102),
+        Some(OpenFlags::from(Access::ReadWrite) | OpenFlags::CREATE | OpenFlags::TRUNC)
+    );
+    assert_eq!(OpenFlags::CREATE.access(), Access::ReadOnly);
+    assert!(OpenFlags::CREATE.is_readable() && !OpenFlags::CREATE.is_writable());
+    assert!(!OpenFlags::from(Access::WriteOnly).is_readable());
+    assert!(OpenFlags::from(Access::ReadWrite).is_writable());
+    let none = OpenFlags::from(Access::NoAccess);
+    assert_eq!(none.access(), Access::NoAccess);
+    assert!(!none.is_readable() && !none.is_writable());
+    let dir = Mode::from(FileKind::Dir) | rw_r_r();
+    assert_eq!(dir.file_type(), Some(FileKind::Dir));
+    assert_eq!(dir.bits(), 0o40644);
+    // A socket's type bits overlap a directory's, but it's still only a socket.
+    let socket = Mode::from(FileKind::Socket) | rw_r_r();
+    assert_eq!(socket.file_type(), Some(FileKind::Socket));
+    assert_ne!(socket.file_type(), Some(FileKind::Dir));
+    assert_eq!(Mode::from_bits(0o644).unwrap().file_type(), None);
+    assert_eq!(GetattrMask::BASIC.bits(), 0x7ff);
+    assert!(GetattrMask::ALL.contains(GetattrMask::BTIME | GetattrMask::DATA_VERSION));
+}
+
+#[test]
+#[cfg(unix)]
+fn std_fs_conversions() {
+    use std::fs::{self, OpenOptions, Permissions};
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::env::temp_dir().join(format!("nine-p2000-l-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("file");
+
+    let flags = OpenFlags::from(Access::WriteOnly) | OpenFlags::CREATE | OpenFlags::EXCL;
+    let mut file = OpenOptions::from(flags).open(&path).unwrap();
+    io::Write::write_all(&mut file, b"nine").unwrap();
+    let err = OpenOptions::from(flags).open(&path).unwrap_err();
+    assert_eq!(Errno::from(err), Errno::EEXIST);
+
+    fs::set_permissions(&path, Mode::from_bits(0o640).unwrap().into()).unwrap();
+    let mode = Mode::from(&fs::metadata(&path).unwrap());
+    assert_eq!(
+        mode,
+        Mode::from(FileKind::Regular) | Mode::from_bits(0o640).unwrap()
+    );
+    assert_eq!(Permissions::from(mode).mode(), 0o640);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,203 @@
+//! Byte-exact tests of the 9P2000.u messages.
+
+extern crate nine;
+
+mod common;
+
+use crate::common::golden;
+use nine::message::{ConstMessageTypeId, Protocol};
+use nine::p2000::u::*;
+use nine::p2000::{pack_dir_entries, DirEntries, NOTAG};
+
+const QID: [u8; 13] = [0, 1, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0];
+
+fn symlink() -> Stat {
+    Stat {
+        type_: 0,
+        dev: 0,
+        qid: Qid {
+            file_type: FileType::SYMLINK,
+            version: 1,
+            path: 2,
+        },
+        mode: FileMode::SYMLINK | FileMode::PERM_MASK,
+        atime: 3,
+        mtime: 4,
+        length: 1,
+        name: "a".to_owned(),
+        uid: "u".to_owned(),
+        gid: "g".to_owned(),
+        muid: "m".to_owned(),
+        extension: "b".to_owned(),
+        n_uid: 1000,
+        n_gid: 1000,
+        n_muid: 1000,
+    }
+}
+
+fn symlink_body() -> Vec<u8> {
+    [
+        &[0, 0, 0, 0, 0, 0][..],
+        &[2, 1, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0],
+        &[0xFF, 1, 0, 2],
+        &[3, 0, 0, 0, 4, 0, 0, 0],
+        &[1, 0, 0, 0, 0, 0, 0, 0],
+        &[1, 0, b'a', 1, 0, b'u', 1, 0, b'g', 1, 0, b'm', 1, 0, b'b'],
+        &[0xE8, 3, 0, 0, 0xE8, 3, 0, 0, 0xE8, 3, 0, 0],
+    ]
+    .concat()
+}
+
+#[test]
+fn auth_and_attach() {
+    golden::<Message, _>(
+        Tversion {
+            tag: NOTAG,
+            msize: 8192,
+            version: P2000U::VERSION.to_owned(),
+        },
+        &[
+            21, 0, 0, 0, 100, 0xFF, 0xFF, 0, 0x20, 0, 0, 8, 0, b'9', b'P', b'2', b'0', b'0', b'0',
+            b'.', b'u',
+        ],
+    );
+    golden::<Message, _>(
+        Tauth {
+            tag: 1,
+            afid: 2,
+            uname: "a".to_owned(),
+            aname: "".to_owned(),
+            n_uname: 1000,
+        },
+        &[
+            20, 0, 0, 0, 102, 1, 0, 2, 0, 0, 0, 1, 0, b'a', 0, 0, 0xE8, 3, 0, 0,
+        ],
+    );
+    golden::<Message, _>(
+        Tattach {
+            tag: 1,
+            fid: 2,
+            afid: !0,
+            uname: "a".to_owned(),
+            aname: "".to_owned(),
+            n_uname: 1000,
+        },
+        &[
+            24, 0, 0, 0, 104, 1, 0, 2, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF, 1, 0, b'a', 0, 0, 0xE8, 3,
+            0, 0,
+        ],
+    );
+}
+
+#[test]
+fn rerror() {
+    golden::<Message, _>(
+        Rerror {
+            tag: 1,
+            ename: "a".to_owned(),
+            errno: Errno::ENOENT,
+        },
+        &[14, 0, 0, 0, 107, 1, 0, 1, 0, b'a', 2, 0, 0, 0],
+    );
+}
+
+#[test]
+fn create() {
+    golden::<Message, _>(
+        Tcreate {
+            tag: 1,
+            fid: 2,
+            name: "a".to_owned(),
+            perm: FileMode::SYMLINK | FileMode::PERM_MASK,
+            mode: OpenMode::READ,
+            extension: "b".to_owned(),
+        },
+        &[
+            22, 0, 0, 0, 114, 1, 0, 2, 0, 0, 0, 1, 0, b'a', 0xFF, 1, 0, 2, 0, 1, 0, b'b',
+        ],
+    );
+}
+
+#[test]
+fn stat() {
+    golden::<Message, _>(
+        Tstat { tag: 1, fid: 2 },
+        &[11, 0, 0, 0, 124, 1, 0, 2, 0, 0, 0],
+    );
+    golden::<Message, _>(
+        Rstat {
+            tag: 1,
+            stat: symlink(),
+        },
+        &[&[77, 0, 0, 0, 125, 1, 0, 68, 0, 66, 0][..], &symlink_body()].concat(),
+    );
+    golden::<Message, _>(
+        Twstat {
+            tag: 1,
+            fid: 2,
+            stat: symlink(),
+        },
+        &[
+            &[81, 0, 0, 0, 126, 1, 0, 2, 0, 0, 0, 68, 0, 66, 0][..],
+            &symlink_body(),
+        ]
+        .concat(),
+    );
+    golden::<Message, _>(Rwstat { tag: 1 }, &[7, 0, 0, 0, 127, 1, 0]);
+
+    assert_eq!(FileType::from(symlink().mode), FileType::SYMLINK);
+}
+
+#[test]
+fn dir_entries() {
+    let stats = vec![symlink(), symlink()];
+    let mut data = Vec::new();
+    let packed = pack_dir_entries(&stats, 8192, &mut data).unwrap();
+    assert_eq!(packed.entries, 2);
+    assert_eq!(data, [&[66, 0][..], &symlink_body()].repeat(2).concat());
+
+    let parsed: Vec<Stat> = DirEntries::with_stats(&data)
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(parsed, stats);
+}
+
+#[test]
+fn openfd() {
+    golden::<Message, _>(
+        Topenfd {
+            tag: 1,
+            fid: 2,
+            mode: OpenMode::RDWR,
+        },
+        &[12, 0, 0, 0, 98, 1, 0, 2, 0, 0, 0, 2],
+    );
+    golden::<Message, _>(
+        Ropenfd {
+            tag: 1,
+            qid: Qid {
+                file_type: FileType::FILE,
+                version: 1,
+                path: 2,
+            },
+            iounit: 0,
+            unixfd: 5,
+        },
+        &[
+            &[28, 0, 0, 0, 99, 1, 0][..],
+            &QID,
+            &[0, 0, 0, 0, 5, 0, 0, 0],
+        ]
+        .concat(),
+    );
+}
+
+#[test]
+fn p2000u_protocol() {
+    assert_eq!(P2000U::response_type_id(Topenfd::MSG_TYPE_ID), Some(99));
+    assert!(P2000U::is_response_to(
+        Tcreate::MSG_TYPE_ID,
+        Rerror::MSG_TYPE_ID
+    ));
+    assert!(P2000U::has_type_id(Tstat::MSG_TYPE_ID));
+}
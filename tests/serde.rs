@@ -61,6 +61,21 @@ fn max_str() {
     assert_eq!(length + 2, string.serialize(&mut serializer).unwrap())
 }
 
+#[test]
+fn failed_append_leaves_bytes_mut_alone() {
+    let mut buf = bytes::BytesMut::from(&b"untouched"[..]);
+    let long_seq = vec!["x".repeat(u16::MAX as usize + 1)];
+
+    assert!(
+        if let SerError::StringTooLong = append_bytes_mut(&long_seq, &mut buf).unwrap_err() {
+            true
+        } else {
+            false
+        }
+    );
+    assert_eq!(&buf[..], b"untouched");
+}
+
 #[test]
 #[ignore]
 fn overlong_bytes() {
@@ -87,3 +102,161 @@ fn max_bytes() {
 
     nine::ser::serialize_bytes(&bytes, &mut serializer).unwrap();
 }
+
+#[test]
+fn enums() {
+    use nine::de::*;
+
+    #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+    enum Shape {
+        Empty,
+        Circle(u32),
+        Line(u16, u16),
+        Rect { w: u8, h: u8 },
+    }
+
+    let cases = [
+        (Shape::Empty, vec![0]),
+        (Shape::Circle(7), vec![1, 7, 0, 0, 0]),
+        (Shape::Line(1, 2), vec![2, 1, 0, 2, 0]),
+        (Shape::Rect { w: 3, h: 4 }, vec![3, 3, 4]),
+    ];
+    for (shape, expected) in &cases {
+        let bytes = into_bytes(shape).unwrap();
+        assert_eq!(&bytes, expected);
+        assert_eq!(into_bytes_mut(shape).unwrap(), bytes);
+        assert_eq!(serialized_size(shape).unwrap() as usize, bytes.len());
+        let mut streamed = Vec::new();
+        into_writer(shape, &mut streamed).unwrap();
+        assert_eq!(streamed, bytes);
+
+        assert_eq!(&from_slice::<Shape>(&bytes).unwrap(), shape);
+        assert_eq!(&from_bytes::<Shape, _>(&bytes).unwrap(), shape);
+        assert_eq!(&from_buf::<Shape, _>(&bytes[..]).unwrap(), shape);
+    }
+
+    assert!(from_slice::<Shape>(&[4]).is_err());
+}
+
+#[test]
+fn overlong_variant_index() {
+    struct Wide;
+
+    impl Serialize for Wide {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_unit_variant("Wide", 256, "Wide")
+        }
+    }
+
+    assert!(
+        if let SerError::VariantIndexTooBig(256) = expect_err(&Wide).unwrap_ser_error() {
+            true
+        } else {
+            false
+        }
+    );
+    assert!(into_bytes_mut(&Wide).is_err());
+    assert!(serialized_size(&Wide).is_err());
+}
+
+#[test]
+fn extended_encodings() {
+    use nine::de::*;
+    use std::collections::BTreeMap;
+    use std::io::Cursor;
+
+    #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+    struct Experiment {
+        present: Option<u16>,
+        absent: Option<u16>,
+        flag: bool,
+        attrs: BTreeMap<String, u8>,
+        mark: char,
+    }
+
+    let mut attrs = BTreeMap::new();
+    attrs.insert("a".to_string(), 1);
+    attrs.insert("b".to_string(), 2);
+    let value = Experiment {
+        present: Some(7),
+        absent: None,
+        flag: true,
+        attrs,
+        mark: 'é',
+    };
+    let expected = [
+        1, 7, 0, // present
+        0, // absent
+        1, // flag
+        2, 0, 1, 0, b'a', 1, 1, 0, b'b', 2, // attrs
+        0xC3, 0xA9, // mark
+    ];
+
+    let mut ser = WriteSerializer::new(Cursor::new(Vec::new())).extended();
+    assert_eq!(value.serialize(&mut ser).unwrap() as usize, expected.len());
+    let bytes = ser.into_writer().into_inner();
+    assert_eq!(bytes, expected);
+    let mut buf = bytes::BytesMut::new();
+    value
+        .serialize(&mut BytesSerializer::new(&mut buf).extended())
+        .unwrap();
+    assert_eq!(&buf[..], &expected[..]);
+    let config = EncoderConfig {
+        extended: true,
+        ..EncoderConfig::default()
+    };
+    assert_eq!(
+        serialized_size_with(&value, &config).unwrap() as usize,
+        bytes.len()
+    );
+    let mut out = Vec::new();
+    into_writer_with(&value, &mut out, &config).unwrap();
+    assert_eq!(out, bytes);
+
+    let mut des = SliceDeserializer::new(&bytes).extended();
+    assert_eq!(Experiment::deserialize(&mut des).unwrap(), value);
+    let mut des = ReadDeserializer::new(&bytes[..]).extended();
+    assert_eq!(Experiment::deserialize(&mut des).unwrap(), value);
+    let mut des = BufDeserializer::new(&bytes[..]).extended();
+    assert_eq!(Experiment::deserialize(&mut des).unwrap(), value);
+
+    // Plain 9p has none of these.
+    assert!(matches!(
+        into_bytes(&value).unwrap_err(),
+        SerError::UnspecifiedType(_)
+    ));
+    assert!(into_bytes_mut(&value).is_err());
+    assert!(serialized_size(&value).is_err());
+    assert!(into_writer(&value, &mut Vec::new()).is_err());
+    for err in &[
+        from_slice::<Experiment>(&bytes).unwrap_err(),
+        from_bytes::<Experiment, _>(&bytes).unwrap_err(),
+        from_buf::<Experiment, _>(&bytes[..]).unwrap_err(),
+    ] {
+        assert!(matches!(err.root(), DeError::UnspecifiedType(_)));
+    }
+
+    let mut des = SliceDeserializer::new(&[2, 7, 0]).extended();
+    assert!(Option::<u16>::deserialize(&mut des).is_err());
+    let mut des = ReadDeserializer::new(&[0xA9][..]).extended();
+    assert!(char::deserialize(&mut des).is_err());
+}
+
+#[test]
+fn bools() {
+    use nine::de::*;
+
+    let bytes = into_bytes(&vec![true, false]).unwrap();
+    assert_eq!(bytes, [2, 0, 1, 0]);
+    assert_eq!(from_slice::<Vec<bool>>(&bytes).unwrap(), [true, false]);
+    assert_eq!(from_bytes::<Vec<bool>, _>(&bytes).unwrap(), [true, false]);
+    assert_eq!(from_buf::<Vec<bool>, _>(&bytes[..]).unwrap(), [true, false]);
+
+    assert!(
+        if let DeError::InvalidByte { value: 2, .. } = from_slice::<bool>(&[2]).unwrap_err() {
+            true
+        } else {
+            false
+        }
+    );
+}